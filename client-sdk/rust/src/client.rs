@@ -1,10 +1,7 @@
-use std::{marker::PhantomData, sync::Arc};
+use std::marker::PhantomData;
 
 use bytes::{Buf as _, BufMut as _};
-use futures_util::{
-    future::try_join_all,
-    stream::{Stream, TryStreamExt},
-};
+use futures_util::stream::{Stream, StreamExt as _, TryStreamExt as _};
 use prost::Message as _;
 use tonic::{self, client::Grpc, transport::Channel};
 
@@ -15,12 +12,18 @@ use oasis_runtime_sdk::{
         consensus::roothash::{AnnotatedBlock, Block},
         transaction::tags::Tag,
     },
-    types::transaction::{
-        AuthInfo, Call, CallFormat, Fee, SignerInfo, Transaction, LATEST_TRANSACTION_VERSION,
-    },
+    types::address::Address,
 };
 
-use crate::{requests::*, types::Round, wallet::Wallet};
+use crate::{
+    middleware::{
+        Claim, Middleware, NonceManager, NonceMiddleware, SignerMiddleware, StaleNonceError,
+        UnsignedTransaction,
+    },
+    requests::*,
+    types::Round,
+    wallet::Wallet,
+};
 
 /// A sentinel value for the latest round.
 const ROUND_LATEST: u64 = u64::max_value();
@@ -29,23 +32,22 @@ const ROUND_LATEST: u64 = u64::max_value();
 /// `oasis-runtime-sdk/tx: v1` followed by the separator, ` for chain `.
 const CHAIN_CONTEXT_PREFIX: &str = "oasis-runtime-sdk/tx: v0 for chain";
 
+/// The base client: talks to the node over gRPC and nothing else. It has no concept of wallets,
+/// nonces, or fees — wrap it in a [`crate::middleware::SignerMiddleware`] (and whatever other
+/// layers are needed) to be able to submit transactions.
 #[derive(Clone)]
 pub struct Client {
     inner: Grpc<Channel>, // Cheap to `Clone`, so no `Arc`
     runtime_id: Namespace,
-    wallets: Arc<Vec<Arc<dyn Wallet>>>,
-    fee: Fee,
     chain_context: Vec<u8>,
 }
 
 impl Client {
-    /// Connects to the oasis-node listening on Unix socket at `sock_path` communicating
-    /// with the identified runtime. Transactions will be signed by the `signer`.
-    /// Do remember to call `set_fee` as appropriate before making the first call.
+    /// Connects to the oasis-node listening on Unix socket at `sock_path` communicating with the
+    /// identified runtime.
     pub async fn connect(
         sock_path: impl AsRef<std::path::Path> + Clone + Send + Sync + 'static,
         runtime_id: Namespace,
-        wallets: impl IntoIterator<Item = Box<dyn Wallet>>,
     ) -> Result<Self, Error> {
         let channel = tonic::transport::Channel::from_static(
             "://.", /* Unused, but required to be a URI. */
@@ -54,13 +56,12 @@ impl Client {
             tokio::net::UnixStream::connect(sock_path.clone())
         }))
         .await?;
-        Self::connect_through_channel(channel, runtime_id, wallets).await
+        Self::connect_through_channel(channel, runtime_id).await
     }
 
     pub async fn connect_through_channel(
         channel: tonic::transport::Channel,
         runtime_id: Namespace,
-        wallets: impl IntoIterator<Item = Box<dyn Wallet>>,
     ) -> Result<Self, Error> {
         let mut grpc = Grpc::new(channel);
 
@@ -73,120 +74,43 @@ impl Client {
         Ok(Self {
             inner: grpc,
             runtime_id,
-            wallets: Arc::new(wallets.into_iter().map(Arc::from).collect()),
-            fee: Default::default(),
             chain_context: chain_context.into_bytes(),
         })
     }
 
-    pub fn set_fee(&mut self, fee: Fee) {
-        self.fee = fee;
-    }
-
-    /// Checks if the oasis-node is ready and accepting connections.
-    pub async fn ready(&mut self) -> Result<(), Error> {
-        Ok(self.inner.ready().await?)
-    }
-
-    /// Returns the block at the requested round.
-    pub async fn get_block(&mut self, round: Round) -> Result<Block, Error> {
-        let req = GetBlockRequest {
-            runtime_id: self.runtime_id,
-            round: match round {
-                Round::Latest => ROUND_LATEST,
-                Round::Numbered(round) => round,
-            },
-        };
-        Ok(self.unary(req).await?)
-    }
-
-    /// Sends an unencrypted transaction to the scheduler.
-    pub async fn tx_plain(&mut self, method: String, body: cbor::Value) -> Result<Vec<u8>, Error> {
-        self.do_tx(method, body, CallFormat::EncryptedX25519DeoxysII)
-            .await
+    /// The runtime chain context derived for this connection, as consumed by `Wallet::sign`.
+    pub fn chain_context(&self) -> &[u8] {
+        &self.chain_context
     }
 
-    /// Sends a transaction to the scheduler.
-    async fn do_tx(
-        &mut self,
-        method: String,
-        body: cbor::Value,
-        format: CallFormat,
-    ) -> Result<Vec<u8>, Error> {
-        let nonces = try_join_all(self.wallets.iter().map(|wallet| wallet.next_nonce()))
-            .await
-            .map_err(Error::Wallet)?;
-        let signer_info = self
-            .wallets
+    /// Builds the default middleware stack for submitting transactions signed by `wallets`,
+    /// backed by a caching `NonceManager` instead of asking each wallet for its next nonce (and
+    /// thus querying the node) on every single submission.
+    pub fn with_nonce_manager(
+        self,
+        wallets: impl IntoIterator<Item = Box<dyn Wallet>>,
+    ) -> NonceMiddleware<SignerMiddleware<Self>, NonceManager<Self>> {
+        let wallets: Vec<Box<dyn Wallet>> = wallets.into_iter().collect();
+        let addresses = wallets
             .iter()
-            .zip(nonces.into_iter())
-            .map(|(wallet, nonce)| SignerInfo {
-                address_spec: wallet.address().clone(),
-                nonce,
-            })
+            .map(|wallet| Address::from_sigspec(wallet.address()))
             .collect();
-        let tx = Transaction {
-            version: LATEST_TRANSACTION_VERSION,
-            call: Call {
-                method,
-                body,
-                format,
-            },
-            auth_info: AuthInfo {
-                signer_info,
-                fee: self.fee.clone(),
-            },
-        };
-        let serialized_tx = cbor::to_vec(tx);
-        let auth_proofs = try_join_all(
-            self.wallets
-                .iter()
-                .map(|wallet| wallet.sign(&self.chain_context, &serialized_tx)),
-        )
-        .await
-        .map_err(Error::Wallet)?;
-        let req = SubmitTxRequest {
-            runtime_id: self.runtime_id,
-            data: cbor::to_vec((serialized_tx, auth_proofs)),
-        };
-        Ok(self.unary(req).await?)
+        let chain_context = self.chain_context.clone();
+        let manager = NonceManager::new(self.clone());
+        let signer = SignerMiddleware::new(self, wallets, chain_context);
+        NonceMiddleware::new(signer, addresses, manager)
     }
 
-    /// Sends a read-only query to connected node.
-    pub async fn query(&mut self, method: &str, body: &cbor::Value) -> Result<cbor::Value, Error> {
-        let req = QueryRequest {
-            runtime_id: self.runtime_id,
-            round: ROUND_LATEST,
-            method: method.to_string(),
-            args: body.clone(),
-        };
-        Ok(self.unary(req).await?.data)
-    }
-
-    /// Sends a request for an event subscription to the connected node.
-    pub async fn watch_blocks(
-        &mut self,
-    ) -> Result<impl Stream<Item = Result<AnnotatedBlock, Error>>, Error> {
-        let block_stream = self
-            .server_streaming(WatchBlocksRequest {
-                runtime_id: self.runtime_id,
-            })
-            .await?;
-        Ok(block_stream.map_err(Into::into))
-    }
-
-    /// Returns the events emitted by the runtime during the provided `round`.
-    pub async fn get_events(&mut self, round: u64) -> Result<Vec<Tag>, Error> {
-        let req = GetEventsRequest {
-            runtime_id: self.runtime_id,
-            round,
-        };
-        let events = self.unary(req).await?;
-        Ok(events.into_iter().map(Into::into).collect())
+    /// Checks if the oasis-node is ready and accepting connections.
+    pub async fn ready(&self) -> Result<(), Error> {
+        let mut channel = self.inner.clone();
+        channel.ready().await?;
+        Ok(())
     }
 
-    async fn unary<R: Request>(&mut self, req: R) -> Result<R::Response, Error> {
-        Self::make_unary(&mut self.inner, req).await
+    async fn unary<R: Request>(&self, req: R) -> Result<R::Response, Error> {
+        let mut channel = self.inner.clone();
+        Self::make_unary(&mut channel, req).await
     }
 
     async fn make_unary<R: Request>(
@@ -205,12 +129,12 @@ impl Client {
     }
 
     async fn server_streaming<R: Request>(
-        &mut self,
+        &self,
         req: R,
     ) -> Result<tonic::codec::Streaming<R::Response>, Error> {
-        self.inner.ready().await?;
-        Ok(self
-            .inner
+        let mut channel = self.inner.clone();
+        channel.ready().await?;
+        Ok(channel
             .server_streaming(
                 tonic::Request::new(req.body()),
                 R::path().parse().unwrap(),
@@ -221,6 +145,94 @@ impl Client {
     }
 }
 
+#[async_trait::async_trait]
+impl Middleware for Client {
+    type Inner = Self;
+    type Error = Error;
+
+    fn inner(&self) -> &Self::Inner {
+        self
+    }
+
+    async fn query(&self, method: &str, body: &cbor::Value) -> Result<cbor::Value, Error> {
+        let req = QueryRequest {
+            runtime_id: self.runtime_id,
+            round: ROUND_LATEST,
+            method: method.to_string(),
+            args: body.clone(),
+        };
+        Ok(self.unary(req).await?.data)
+    }
+
+    async fn get_block(&self, round: Round) -> Result<Block, Error> {
+        let req = GetBlockRequest {
+            runtime_id: self.runtime_id,
+            round: match round {
+                Round::Latest => ROUND_LATEST,
+                Round::Numbered(round) => round,
+            },
+        };
+        self.unary(req).await
+    }
+
+    async fn get_events(&self, round: u64) -> Result<Vec<Tag>, Error> {
+        let req = GetEventsRequest {
+            runtime_id: self.runtime_id,
+            round,
+        };
+        let events = self.unary(req).await?;
+        Ok(events.into_iter().map(Into::into).collect())
+    }
+
+    async fn watch_blocks(
+        &self,
+    ) -> Result<std::pin::Pin<Box<dyn Stream<Item = Result<AnnotatedBlock, Error>> + Send>>, Error>
+    {
+        let block_stream = self
+            .server_streaming(WatchBlocksRequest {
+                runtime_id: self.runtime_id,
+            })
+            .await?;
+        Ok(Box::pin(block_stream.map_err(Into::into)))
+    }
+
+    /// The base client has no signer, so it can't turn an `UnsignedTransaction` into something
+    /// submittable on its own; wrap it in a `SignerMiddleware` first.
+    async fn submit_transaction(&self, _tx: UnsignedTransaction) -> Result<Vec<u8>, Error> {
+        Err(Error::Unsigned)
+    }
+
+    async fn submit_tx_raw(&self, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let req = SubmitTxRequest {
+            runtime_id: self.runtime_id,
+            data,
+        };
+        self.unary(req).await
+    }
+
+    /// The base client has no signer, so it can't turn an `UnsignedTransaction` into something
+    /// submittable on its own; wrap it in a `SignerMiddleware` first.
+    async fn submit_tx_nowait(&self, _tx: UnsignedTransaction) -> Result<Claim, Error> {
+        Err(Error::Unsigned)
+    }
+
+    async fn submit_tx_raw_nowait(&self, data: Vec<u8>) -> Result<Claim, Error> {
+        let tx_hash = Hash::digest_bytes(&data);
+        let round_submitted = self.get_block(Round::Latest).await?.header.round;
+
+        let req = SubmitTxNoWaitRequest {
+            runtime_id: self.runtime_id,
+            data,
+        };
+        self.unary(req).await?;
+
+        Ok(Claim {
+            tx_hash,
+            round_submitted,
+        })
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     /// An RPC transport error occured (e.g., could not connect to Unix socket).
@@ -253,6 +265,10 @@ pub enum Error {
         /// The error message, if provided by the module.
         message: Option<String>,
     },
+
+    /// `submit_transaction` was called on a client with no `SignerMiddleware` layer.
+    #[error("cannot submit an unsigned transaction without a SignerMiddleware layer")]
+    Unsigned,
 }
 
 impl Error {
@@ -265,6 +281,21 @@ impl Error {
     }
 }
 
+/// The module name and error code the `accounts` module uses to reject a transaction whose nonce
+/// doesn't match the signer's current on-chain nonce.
+const ACCOUNTS_MODULE_NAME: &str = "accounts";
+const ACCOUNTS_ERROR_CODE_INVALID_NONCE: u32 = 3;
+
+impl StaleNonceError for Error {
+    fn is_stale_nonce(&self) -> bool {
+        matches!(
+            self,
+            Self::RequestFailed { module, code, .. }
+                if module == ACCOUNTS_MODULE_NAME && *code == ACCOUNTS_ERROR_CODE_INVALID_NONCE
+        )
+    }
+}
+
 /// @see `oasis-core/go/common/errors/errors.go`
 #[derive(Debug, cbor::Decode)]
 struct CodedError {
@@ -371,4 +402,4 @@ impl<T: cbor::Decode + Send + Sync> tonic::codec::Decoder for CborDecoder<T> {
         src.copy_to_slice(&mut src_buf);
         cbor::from_slice(&src_buf).map_err(|e| tonic::Status::internal(e.to_string()))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,35 @@
+//! Decouples submitting a transaction from confirming it, borrowing the submit/claim/confirm
+//! split from Serai's Eventuality design: `submit_tx_nowait` hands the transaction to the node's
+//! mempool and returns as soon as it's been accepted, instead of blocking until it lands in a
+//! block; the returned [`Claim`] is later passed to `confirm` to resolve how (and whether) it was
+//! actually included. This lets a caller fire off many transactions and await their claims
+//! concurrently, rather than serializing on `submit_transaction`'s blocking round trip.
+use oasis_runtime_sdk::core::common::crypto::hash::Hash;
+
+/// A lightweight receipt for a transaction submitted via `submit_tx_nowait`. Doesn't guarantee
+/// inclusion — pass it to `confirm` to find out.
+#[derive(Clone, Debug)]
+pub struct Claim {
+    /// The hash of the submitted transaction, as tagged on the events it emits once included.
+    pub tx_hash: Hash,
+    /// The round the transaction was submitted at. `confirm` only needs to watch blocks from
+    /// this round onward.
+    pub round_submitted: u64,
+}
+
+/// Errors raised by [`super::Middleware::confirm`].
+#[derive(Debug, thiserror::Error)]
+pub enum ConfirmError<E: std::error::Error + 'static> {
+    /// An error from the wrapped middleware while watching blocks or fetching events.
+    #[error(transparent)]
+    Inner(#[from] E),
+
+    /// The claimed transaction's `CallResult` tag couldn't be decoded.
+    #[error("failed to decode call result: {0}")]
+    Decode(#[from] cbor::DecodeError),
+
+    /// The transaction hadn't appeared in any block's events after watching the requested number
+    /// of rounds.
+    #[error("timed out waiting for confirmation after {0} rounds")]
+    Timeout(u64),
+}
@@ -0,0 +1,61 @@
+//! A middleware layer that sets the fee on every transaction it submits.
+use oasis_runtime_sdk::types::transaction::Fee;
+
+use super::{Middleware, StaleNonceError, UnsignedTransaction};
+
+/// A middleware layer that stamps every [`UnsignedTransaction`] passing through it with a fixed
+/// fee before forwarding it to `inner()`. Typically the outermost layer in the stack, so that
+/// users can call `client.set_fee(..)` without reaching into the signer or nonce layers beneath
+/// it.
+pub struct FeeMiddleware<M> {
+    inner: M,
+    fee: Fee,
+}
+
+impl<M: Middleware> FeeMiddleware<M> {
+    /// Creates a new `FeeMiddleware` that stamps `fee` onto every transaction.
+    pub fn new(inner: M, fee: Fee) -> Self {
+        Self { inner, fee }
+    }
+
+    /// Updates the fee applied to subsequently submitted transactions.
+    pub fn set_fee(&mut self, fee: Fee) {
+        self.fee = fee;
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> Middleware for FeeMiddleware<M>
+where
+    FeeError<M::Error>: From<M::Error>,
+{
+    type Inner = M;
+    type Error = FeeError<M::Error>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn submit_transaction(&self, mut tx: UnsignedTransaction) -> Result<Vec<u8>, Self::Error> {
+        tx.fee = Some(self.fee.clone());
+        Ok(self.inner.submit_transaction(tx).await?)
+    }
+}
+
+/// Errors raised by [`FeeMiddleware`]. `FeeMiddleware` never fails on its own, so this is always
+/// just the wrapped middleware's error.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct FeeError<E: std::error::Error + 'static>(E);
+
+impl<E: std::error::Error + 'static> From<E> for FeeError<E> {
+    fn from(e: E) -> Self {
+        Self(e)
+    }
+}
+
+impl<E: std::error::Error + StaleNonceError + 'static> StaleNonceError for FeeError<E> {
+    fn is_stale_nonce(&self) -> bool {
+        self.0.is_stale_nonce()
+    }
+}
@@ -0,0 +1,187 @@
+//! A middleware layer that estimates gas automatically instead of requiring a manually
+//! configured fee.
+use oasis_runtime_sdk::types::{
+    token,
+    transaction::{AddressSpec, AuthInfo, Call, Fee, SignerInfo, Transaction, LATEST_TRANSACTION_VERSION},
+};
+
+use super::{Middleware, StaleNonceError, UnsignedTransaction};
+
+/// Estimates the fee a transaction should carry. Implementations are free to use whatever
+/// pricing strategy they like — query the node, apply a fixed price, cap gas at a ceiling — as
+/// long as they can look at the draft `Transaction` being estimated.
+#[async_trait::async_trait]
+pub trait GasOracle: Send + Sync {
+    /// Estimates the fee for `tx`. `tx.auth_info.fee` is a placeholder (`Fee::default()`) and
+    /// should be ignored; everything else is as it will be submitted.
+    async fn estimate_fee(&self, tx: &Transaction) -> Result<Fee, anyhow::Error>;
+}
+
+/// A [`GasOracle`] that always returns the same fee, regardless of the transaction. Useful for
+/// tests, or for chains where a flat fee is acceptable.
+pub struct FixedFeeOracle(pub Fee);
+
+#[async_trait::async_trait]
+impl GasOracle for FixedFeeOracle {
+    async fn estimate_fee(&self, _tx: &Transaction) -> Result<Fee, anyhow::Error> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A [`GasOracle`] that asks the node's `core.EstimateGas` query method to estimate gas usage for
+/// the draft transaction, then multiplies by a configured gas price. Optionally caps the
+/// estimate at a fixed ceiling, so a pathological estimate can't produce an unreasonable fee.
+pub struct QueryGasOracle<Q> {
+    node: Q,
+    gas_price: u128,
+    denomination: token::Denomination,
+    gas_cap: Option<u64>,
+}
+
+impl<Q: Middleware> QueryGasOracle<Q> {
+    /// Creates an oracle charging `gas_price` units of `denomination` per unit of gas, with no
+    /// cap on the estimate. Use `with_gas_cap` to add one.
+    pub fn new(node: Q, gas_price: u128, denomination: token::Denomination) -> Self {
+        Self {
+            node,
+            gas_price,
+            denomination,
+            gas_cap: None,
+        }
+    }
+
+    /// Caps any gas estimate at `cap`, regardless of what the node reports.
+    pub fn with_gas_cap(mut self, cap: u64) -> Self {
+        self.gas_cap = Some(cap);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<Q: Middleware> GasOracle for QueryGasOracle<Q> {
+    async fn estimate_fee(&self, tx: &Transaction) -> Result<Fee, anyhow::Error> {
+        let body = cbor::to_value(tx.clone());
+        let result = self
+            .node
+            .query("core.EstimateGas", &body)
+            .await
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        let mut gas: u64 =
+            cbor::from_value(result).map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        if let Some(cap) = self.gas_cap {
+            gas = gas.min(cap);
+        }
+
+        Ok(Fee {
+            amount: token::BaseUnits::new(
+                self.gas_price.saturating_mul(gas as u128),
+                self.denomination.clone(),
+            ),
+            gas,
+            consensus_messages: 0,
+        })
+    }
+}
+
+/// A middleware layer that fills in `tx.fee` with a [`GasOracle`] estimate whenever the caller
+/// (or an outer `FeeMiddleware`) hasn't already pinned one. Place it just outside
+/// `SignerMiddleware` — after `NonceMiddleware`, if present, so the estimate reflects the nonces
+/// the transaction will actually be signed with.
+pub struct GasOracleMiddleware<M, O> {
+    inner: M,
+    oracle: O,
+    signers: Vec<AddressSpec>,
+}
+
+impl<M: Middleware, O: GasOracle> GasOracleMiddleware<M, O> {
+    /// Creates a new `GasOracleMiddleware` estimating for a transaction signed by `signers` (in
+    /// the same order `SignerMiddleware` will sign in).
+    pub fn new(inner: M, signers: Vec<AddressSpec>, oracle: O) -> Self {
+        Self {
+            inner,
+            oracle,
+            signers,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware, O: GasOracle> Middleware for GasOracleMiddleware<M, O>
+where
+    GasOracleError<M::Error>: From<M::Error>,
+{
+    type Inner = M;
+    type Error = GasOracleError<M::Error>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn submit_transaction(&self, mut tx: UnsignedTransaction) -> Result<Vec<u8>, Self::Error> {
+        if tx.fee.is_none() {
+            let signer_info = self
+                .signers
+                .iter()
+                .enumerate()
+                .map(|(i, address_spec)| SignerInfo {
+                    address_spec: address_spec.clone(),
+                    nonce: tx.nonces.get(i).copied().flatten().unwrap_or_default(),
+                })
+                .collect();
+            let draft = Transaction {
+                version: LATEST_TRANSACTION_VERSION,
+                call: clone_call(&tx.call),
+                auth_info: AuthInfo {
+                    signer_info,
+                    fee: Fee::default(),
+                },
+                not_before: None,
+                not_after: None,
+            };
+            tx.fee = Some(
+                self.oracle
+                    .estimate_fee(&draft)
+                    .await
+                    .map_err(GasOracleError::Oracle)?,
+            );
+        }
+        Ok(self.inner.submit_transaction(tx).await?)
+    }
+}
+
+/// Copies `call` field by field for the draft transaction handed to the oracle, leaving `tx.call`
+/// itself in place for submission.
+fn clone_call(call: &Call) -> Call {
+    Call {
+        format: call.format,
+        method: call.method.clone(),
+        body: call.body.clone(),
+    }
+}
+
+/// Errors raised by [`GasOracleMiddleware`].
+#[derive(Debug, thiserror::Error)]
+pub enum GasOracleError<E: std::error::Error + 'static> {
+    /// An error from the wrapped middleware.
+    #[error(transparent)]
+    Inner(E),
+
+    /// The configured `GasOracle` failed to produce an estimate.
+    #[error("gas oracle error: {0}")]
+    Oracle(anyhow::Error),
+}
+
+impl<E: std::error::Error + 'static> From<E> for GasOracleError<E> {
+    fn from(e: E) -> Self {
+        Self::Inner(e)
+    }
+}
+
+impl<E: std::error::Error + StaleNonceError + 'static> StaleNonceError for GasOracleError<E> {
+    fn is_stale_nonce(&self) -> bool {
+        match self {
+            Self::Inner(e) => e.is_stale_nonce(),
+            Self::Oracle(_) => false,
+        }
+    }
+}
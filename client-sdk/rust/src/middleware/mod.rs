@@ -0,0 +1,189 @@
+//! A composable middleware stack for [`crate::client::Client`], modeled on the `Middleware`
+//! trait from `ethers-rs`.
+//!
+//! The base [`crate::client::Client`] only speaks to the node: it has no concept of wallets,
+//! nonces, or fees. Each of those is instead a layer that wraps an inner [`Middleware`] and
+//! augments one piece of an in-progress [`UnsignedTransaction`] before delegating the rest of the
+//! call to `inner()`. Layers compose in the order they're nested, e.g.
+//!
+//! ```ignore
+//! FeeMiddleware::new(NonceMiddleware::new(SignerMiddleware::new(client, wallets, chain_context), addresses, source), fee)
+//! ```
+//!
+//! wraps `Signer` (innermost, does the actual signing) in `Nonce` (assigns nonces) in `Fee`
+//! (outermost, sets the fee). New cross-cutting concerns — retries, logging, rate limiting — are
+//! added the same way, as one more layer, instead of edits to the base client.
+use std::pin::Pin;
+
+use futures_util::stream::Stream;
+
+use oasis_runtime_sdk::{
+    core::consensus::roothash::{AnnotatedBlock, Block},
+    core::transaction::tags::Tag,
+    types::transaction::{Call, CallFormat, CallResult, Fee},
+};
+
+use crate::types::Round;
+
+mod claim;
+mod fee;
+mod gas_oracle;
+mod nonce;
+mod signer;
+
+pub use claim::{Claim, ConfirmError};
+pub use fee::{FeeError, FeeMiddleware};
+pub use gas_oracle::{FixedFeeOracle, GasOracle, GasOracleError, GasOracleMiddleware, QueryGasOracle};
+pub use nonce::{NonceError, NonceManager, NonceMiddleware, NonceSource, WalletNonceSource};
+pub use signer::{SignerError, SignerMiddleware};
+
+/// A method call that hasn't yet been assigned signers, nonces, or a fee. Middleware layers fill
+/// in their piece as the call travels down the stack; the innermost [`SignerMiddleware`]
+/// finalizes it into a signed, serialized transaction.
+#[derive(Clone, Debug)]
+pub struct UnsignedTransaction {
+    /// The method call itself.
+    pub call: Call,
+    /// Nonce to use for each of `SignerMiddleware`'s wallets, in wallet order. An entry that's
+    /// `None` (or simply missing) means "let `SignerMiddleware` pick one itself", which it does
+    /// by calling that wallet's own `next_nonce`.
+    pub nonces: Vec<Option<u64>>,
+    /// The fee the signers are willing to pay to have the transaction included. `None` means no
+    /// layer has picked one yet: a `GasOracleMiddleware` will estimate one if present, and
+    /// `SignerMiddleware` falls back to `Fee::default()` if it's still unset by the time the
+    /// transaction is finalized. A `FeeMiddleware` always overrides this with its fixed fee,
+    /// taking priority over the oracle.
+    pub fee: Option<Fee>,
+}
+
+impl UnsignedTransaction {
+    /// Creates a bare call with no nonces and no fee assigned yet.
+    pub fn new(call: Call) -> Self {
+        Self {
+            call,
+            nonces: Vec::new(),
+            fee: None,
+        }
+    }
+}
+
+/// Implemented by every middleware stack's error type so that a layer can detect an
+/// invalid/stale-nonce rejection no matter how deep in the stack it originated, without needing
+/// to know the concrete error type of every layer beneath it.
+pub trait StaleNonceError {
+    /// Returns `true` if this error represents the node rejecting a transaction because one of
+    /// its nonces was invalid or stale.
+    fn is_stale_nonce(&self) -> bool;
+}
+
+/// A stackable layer over the base client. Every operation has a default implementation that
+/// simply forwards to `inner()`, so a layer only needs to override the one it augments.
+#[async_trait::async_trait]
+pub trait Middleware: Send + Sync
+where
+    Self::Error: From<<Self::Inner as Middleware>::Error>,
+{
+    /// The middleware (or base client) this layer wraps.
+    type Inner: Middleware;
+    /// This layer's error type. Must be constructible from the inner layer's error so that `?`
+    /// can be used in default method bodies that delegate to `inner()`.
+    type Error: std::error::Error + StaleNonceError + Send + Sync + 'static;
+
+    /// Returns the middleware this layer wraps.
+    fn inner(&self) -> &Self::Inner;
+
+    /// Sends a read-only query to the connected node.
+    async fn query(&self, method: &str, body: &cbor::Value) -> Result<cbor::Value, Self::Error> {
+        Ok(self.inner().query(method, body).await?)
+    }
+
+    /// Returns the block at the requested round.
+    async fn get_block(&self, round: Round) -> Result<Block, Self::Error> {
+        Ok(self.inner().get_block(round).await?)
+    }
+
+    /// Returns the events emitted by the runtime during the provided round.
+    async fn get_events(&self, round: u64) -> Result<Vec<Tag>, Self::Error> {
+        Ok(self.inner().get_events(round).await?)
+    }
+
+    /// Subscribes to new blocks as they're finalized.
+    async fn watch_blocks(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<AnnotatedBlock, Self::Error>> + Send>>, Self::Error>
+    {
+        let inner_stream = self.inner().watch_blocks().await?;
+        Ok(Box::pin(futures_util::StreamExt::map(
+            inner_stream,
+            |item| item.map_err(Self::Error::from),
+        )))
+    }
+
+    /// Submits an in-progress call, filling in whichever piece of it this layer owns before
+    /// delegating to `inner()`. The innermost `SignerMiddleware` finalizes and signs it; the base
+    /// client serializes it and posts it to the node.
+    async fn submit_transaction(&self, tx: UnsignedTransaction) -> Result<Vec<u8>, Self::Error> {
+        Ok(self.inner().submit_transaction(tx).await?)
+    }
+
+    /// Submits an already-signed, CBOR-serialized `(Transaction, Vec<SignatureProof>)` pair to
+    /// the scheduler and returns its raw result bytes.
+    async fn submit_tx_raw(&self, data: Vec<u8>) -> Result<Vec<u8>, Self::Error> {
+        Ok(self.inner().submit_tx_raw(data).await?)
+    }
+
+    /// Like `submit_transaction`, but returns a [`Claim`] as soon as the node accepts the
+    /// transaction into its mempool, without waiting for it to be included in a block. Pass the
+    /// claim to `confirm` later to find out what happened.
+    async fn submit_tx_nowait(&self, tx: UnsignedTransaction) -> Result<Claim, Self::Error> {
+        Ok(self.inner().submit_tx_nowait(tx).await?)
+    }
+
+    /// Like `submit_tx_raw`, but non-blocking; see `submit_tx_nowait`.
+    async fn submit_tx_raw_nowait(&self, data: Vec<u8>) -> Result<Claim, Self::Error> {
+        Ok(self.inner().submit_tx_raw_nowait(data).await?)
+    }
+
+    /// Resolves the eventuality of a transaction submitted via `submit_tx_nowait`. Drives
+    /// `watch_blocks` forward starting at `claim.round_submitted`, and for each new block calls
+    /// `get_events` looking for the tag keyed by `claim.tx_hash`. Gives up, returning
+    /// `ConfirmError::Timeout`, if the transaction hasn't appeared after `max_rounds` rounds.
+    async fn confirm(
+        &self,
+        claim: &Claim,
+        max_rounds: u64,
+    ) -> Result<CallResult, ConfirmError<Self::Error>> {
+        use futures_util::StreamExt as _;
+
+        let mut blocks = self.watch_blocks().await?;
+        let mut rounds_seen = 0u64;
+        while let Some(block) = blocks.next().await.transpose()? {
+            let round = block.block.header.round;
+            if round < claim.round_submitted {
+                continue;
+            }
+            for tag in self.get_events(round).await? {
+                if tag.tx_hash == claim.tx_hash {
+                    return Ok(cbor::from_slice(&tag.value)?);
+                }
+            }
+            rounds_seen += 1;
+            if rounds_seen >= max_rounds {
+                break;
+            }
+        }
+        Err(ConfirmError::Timeout(max_rounds))
+    }
+
+    /// Sends a transaction whose call carries `body` as given, with the call format set to
+    /// `CallFormat::EncryptedX25519DeoxysII`, and returns its raw result bytes. No layer here
+    /// encrypts `body`.
+    async fn tx_plain(&self, method: String, body: cbor::Value) -> Result<Vec<u8>, Self::Error> {
+        let call = Call {
+            method,
+            body,
+            format: CallFormat::EncryptedX25519DeoxysII,
+        };
+        self.submit_transaction(UnsignedTransaction::new(call)).await
+    }
+}
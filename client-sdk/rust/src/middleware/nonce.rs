@@ -0,0 +1,240 @@
+//! A middleware layer that assigns nonces to an in-progress transaction.
+use std::collections::BTreeMap;
+
+use futures_util::future::try_join_all;
+use tokio::sync::Mutex;
+
+use oasis_runtime_sdk::types::address::Address;
+
+use super::{Claim, Middleware, StaleNonceError, UnsignedTransaction};
+
+/// A source of nonces, keyed by signer address. `NonceMiddleware` asks this for one nonce per
+/// signer address on every submission.
+#[async_trait::async_trait]
+pub trait NonceSource: Send + Sync {
+    /// Returns the next nonce to use for `address`.
+    async fn next_nonce(&self, address: &Address) -> Result<u64, anyhow::Error>;
+
+    /// Called when a transaction carrying `nonce` for `address` failed to submit for a reason
+    /// unrelated to the nonce itself, so the source can make `nonce` available again instead of
+    /// leaving a gap that would stall every later transaction for `address`. The default does
+    /// nothing, which is correct for a source like `WalletNonceSource` that never gets ahead of
+    /// the chain in the first place.
+    async fn release(&self, _address: &Address, _nonce: u64) {}
+
+    /// Called when a submission was rejected because `address`'s nonce was stale, so any cached
+    /// state for it should be dropped and re-derived from the chain on the next `next_nonce`
+    /// call. The default does nothing, for the same reason as `release`.
+    async fn invalidate(&self, _address: &Address) {}
+}
+
+/// A [`NonceSource`] that defers to each wallet's own `next_nonce`, preserving the original
+/// per-submission behavior. Used when no caching nonce manager has been configured.
+pub struct WalletNonceSource {
+    wallets: std::sync::Arc<Vec<std::sync::Arc<dyn crate::wallet::Wallet>>>,
+}
+
+impl WalletNonceSource {
+    /// Creates a source that looks up `address` among `wallets` and defers to its `next_nonce`.
+    pub fn new(wallets: std::sync::Arc<Vec<std::sync::Arc<dyn crate::wallet::Wallet>>>) -> Self {
+        Self { wallets }
+    }
+}
+
+#[async_trait::async_trait]
+impl NonceSource for WalletNonceSource {
+    async fn next_nonce(&self, address: &Address) -> Result<u64, anyhow::Error> {
+        let wallet = self
+            .wallets
+            .iter()
+            .find(|wallet| Address::from_sigspec(wallet.address()) == *address)
+            .ok_or_else(|| anyhow::anyhow!("no wallet for address {:?}", address))?;
+        wallet.next_nonce().await
+    }
+}
+
+/// Request body for the `accounts.Nonce` query: the next nonce the node expects from `address`.
+#[derive(Clone, Debug, cbor::Encode)]
+struct NonceQuery {
+    address: Address,
+}
+
+/// A [`NonceSource`] that fetches each signer's on-chain nonce once and then hands out
+/// monotonically increasing nonces locally under a mutex, so that many transactions for the same
+/// signer can be assembled and submitted back-to-back instead of serializing on a round-trip to
+/// the node for every single one.
+///
+/// Ported from the nonce-manager in `ethers-rs` and the account-scheduler "nonce uses" tracking
+/// in Serai: the cache is the source of truth for "what's the next nonce" once it's warm, and the
+/// node is only queried again for an address after `invalidate` has dropped its cached entry
+/// (which `NonceMiddleware` does when the node rejects a nonce as stale).
+pub struct NonceManager<Q> {
+    node: Q,
+    cache: Mutex<BTreeMap<Address, u64>>,
+}
+
+impl<Q: Middleware> NonceManager<Q> {
+    /// Creates a new, empty `NonceManager` that queries `node` for on-chain nonces as needed.
+    pub fn new(node: Q) -> Self {
+        Self {
+            node,
+            cache: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Queries the node for `address`'s current on-chain nonce.
+    async fn fetch_onchain_nonce(&self, address: &Address) -> Result<u64, anyhow::Error> {
+        let body = cbor::to_value(NonceQuery {
+            address: address.clone(),
+        });
+        let result = self
+            .node
+            .query("accounts.Nonce", &body)
+            .await
+            .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+        cbor::from_value(result).map_err(|e| anyhow::anyhow!(e.to_string()))
+    }
+}
+
+#[async_trait::async_trait]
+impl<Q: Middleware> NonceSource for NonceManager<Q> {
+    async fn next_nonce(&self, address: &Address) -> Result<u64, anyhow::Error> {
+        let mut cache = self.cache.lock().await;
+        if let Some(next) = cache.get_mut(address) {
+            let nonce = *next;
+            *next += 1;
+            return Ok(nonce);
+        }
+
+        // Cold cache: fetch the on-chain nonce once, then start handing out the ones after it
+        // locally. Held across the await so two concurrent callers for the same fresh address
+        // can't both fetch and both claim the same on-chain nonce.
+        let nonce = self.fetch_onchain_nonce(address).await?;
+        cache.insert(address.clone(), nonce + 1);
+        Ok(nonce)
+    }
+
+    async fn release(&self, address: &Address, nonce: u64) {
+        let mut cache = self.cache.lock().await;
+        // Only roll back if nothing has claimed a later nonce for this address in the meantime;
+        // otherwise rolling back would hand the same nonce out twice.
+        if cache.get(address) == Some(&(nonce + 1)) {
+            cache.insert(address.clone(), nonce);
+        }
+    }
+
+    async fn invalidate(&self, address: &Address) {
+        self.cache.lock().await.remove(address);
+    }
+}
+
+/// A middleware layer that assigns a nonce to each of a fixed list of signer `addresses` before
+/// forwarding the call to `inner()` (typically a `SignerMiddleware` signing for those same
+/// addresses). Swap in a caching `NonceManager` as the `source` to pipeline concurrent
+/// transactions instead of serializing on a fresh on-chain nonce lookup each time.
+pub struct NonceMiddleware<M, S> {
+    inner: M,
+    source: S,
+    addresses: Vec<Address>,
+}
+
+impl<M: Middleware, S: NonceSource> NonceMiddleware<M, S> {
+    /// Creates a new `NonceMiddleware` assigning nonces for `addresses` from `source`.
+    pub fn new(inner: M, addresses: Vec<Address>, source: S) -> Self {
+        Self {
+            inner,
+            source,
+            addresses,
+        }
+    }
+
+    /// Assigns a fresh nonce per `addresses` from `source` and stamps them onto `tx`. Shared by
+    /// `submit_transaction` and `submit_tx_nowait`, which differ only in what they do with the
+    /// resulting transaction.
+    async fn assign_nonces(&self, tx: &mut UnsignedTransaction) -> Result<Vec<u64>, anyhow::Error> {
+        let nonces = try_join_all(self.addresses.iter().map(|address| self.source.next_nonce(address)))
+            .await?;
+        tx.nonces = nonces.iter().copied().map(Some).collect();
+        Ok(nonces)
+    }
+
+    /// Reacts to a submission failure for a transaction carrying `nonces`: drops the cached
+    /// nonces entirely if the node rejected them as stale, otherwise hands them back so a
+    /// transient failure doesn't leave a permanent gap that stalls every later transaction for
+    /// these signers.
+    async fn cleanup_nonces(&self, err: &M::Error, nonces: &[u64]) {
+        if err.is_stale_nonce() {
+            for address in &self.addresses {
+                self.source.invalidate(address).await;
+            }
+        } else {
+            for (address, nonce) in self.addresses.iter().zip(nonces.iter().copied()) {
+                self.source.release(address, nonce).await;
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware, S: NonceSource> Middleware for NonceMiddleware<M, S>
+where
+    NonceError<M::Error>: From<M::Error>,
+{
+    type Inner = M;
+    type Error = NonceError<M::Error>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn submit_transaction(&self, mut tx: UnsignedTransaction) -> Result<Vec<u8>, Self::Error> {
+        let nonces = self.assign_nonces(&mut tx).await.map_err(NonceError::Source)?;
+
+        match self.inner.submit_transaction(tx).await {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                self.cleanup_nonces(&err, &nonces).await;
+                Err(err.into())
+            }
+        }
+    }
+
+    async fn submit_tx_nowait(&self, mut tx: UnsignedTransaction) -> Result<Claim, Self::Error> {
+        let nonces = self.assign_nonces(&mut tx).await.map_err(NonceError::Source)?;
+
+        match self.inner.submit_tx_nowait(tx).await {
+            Ok(result) => Ok(result),
+            Err(err) => {
+                self.cleanup_nonces(&err, &nonces).await;
+                Err(err.into())
+            }
+        }
+    }
+}
+
+/// Errors raised by [`NonceMiddleware`].
+#[derive(Debug, thiserror::Error)]
+pub enum NonceError<E: std::error::Error + 'static> {
+    /// An error from the wrapped middleware.
+    #[error(transparent)]
+    Inner(E),
+
+    /// The configured `NonceSource` failed to produce a nonce.
+    #[error("nonce source error: {0}")]
+    Source(anyhow::Error),
+}
+
+impl<E: std::error::Error + 'static> From<E> for NonceError<E> {
+    fn from(e: E) -> Self {
+        Self::Inner(e)
+    }
+}
+
+impl<E: std::error::Error + StaleNonceError + 'static> StaleNonceError for NonceError<E> {
+    fn is_stale_nonce(&self) -> bool {
+        match self {
+            Self::Inner(e) => e.is_stale_nonce(),
+            Self::Source(_) => false,
+        }
+    }
+}
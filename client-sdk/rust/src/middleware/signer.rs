@@ -0,0 +1,140 @@
+//! The innermost middleware layer: owns the wallets and chain context, and does the actual
+//! signing.
+use std::sync::Arc;
+
+use futures_util::future::try_join_all;
+
+use oasis_runtime_sdk::types::transaction::{
+    AuthInfo, SignerInfo, Transaction, LATEST_TRANSACTION_VERSION,
+};
+
+use super::{Claim, Middleware, StaleNonceError, UnsignedTransaction};
+use crate::wallet::Wallet;
+
+/// A middleware layer that owns the transaction signers and finalizes an [`UnsignedTransaction`]
+/// into a signed, serialized one before handing it to `inner()` for submission.
+pub struct SignerMiddleware<M> {
+    inner: M,
+    wallets: Arc<Vec<Arc<dyn Wallet>>>,
+    chain_context: Vec<u8>,
+}
+
+impl<M: Middleware> SignerMiddleware<M> {
+    /// Creates a new `SignerMiddleware` signing with `wallets`, over the given `chain_context`
+    /// (as produced by `Client::connect`).
+    pub fn new(
+        inner: M,
+        wallets: impl IntoIterator<Item = Box<dyn Wallet>>,
+        chain_context: Vec<u8>,
+    ) -> Self {
+        Self {
+            inner,
+            wallets: Arc::new(wallets.into_iter().map(Arc::from).collect()),
+            chain_context,
+        }
+    }
+
+    /// The wallets this layer signs with, in signing order.
+    pub fn wallets(&self) -> &Arc<Vec<Arc<dyn Wallet>>> {
+        &self.wallets
+    }
+
+    /// Assigns nonces (falling back to each wallet's own `next_nonce` where `tx` didn't already
+    /// pin one), builds and signs the transaction, and serializes the resulting
+    /// `(Transaction, Vec<SignatureProof>)` pair. Shared by `submit_transaction` and
+    /// `submit_tx_nowait`, which differ only in what they do with the serialized bytes.
+    async fn finalize(&self, tx: UnsignedTransaction) -> Result<Vec<u8>, SignerError<M::Error>> {
+        let nonces = try_join_all(self.wallets.iter().enumerate().map(|(i, wallet)| async move {
+            match tx.nonces.get(i).copied().flatten() {
+                Some(nonce) => Ok(nonce),
+                None => wallet.next_nonce().await,
+            }
+        }))
+        .await
+        .map_err(SignerError::Wallet)?;
+
+        let signer_info = self
+            .wallets
+            .iter()
+            .zip(nonces)
+            .map(|(wallet, nonce)| SignerInfo {
+                address_spec: wallet.address().clone(),
+                nonce,
+            })
+            .collect();
+
+        let transaction = Transaction {
+            version: LATEST_TRANSACTION_VERSION,
+            call: tx.call,
+            auth_info: AuthInfo {
+                signer_info,
+                fee: tx.fee.unwrap_or_default(),
+            },
+            not_before: None,
+            not_after: None,
+        };
+        let serialized_tx = cbor::to_vec(transaction);
+        let auth_proofs = try_join_all(
+            self.wallets
+                .iter()
+                .map(|wallet| wallet.sign(&self.chain_context, &serialized_tx)),
+        )
+        .await
+        .map_err(SignerError::Wallet)?;
+
+        Ok(cbor::to_vec((serialized_tx, auth_proofs)))
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> Middleware for SignerMiddleware<M>
+where
+    SignerError<M::Error>: From<M::Error>,
+{
+    type Inner = M;
+    type Error = SignerError<M::Error>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn submit_transaction(&self, tx: UnsignedTransaction) -> Result<Vec<u8>, Self::Error> {
+        let data = self.finalize(tx).await?;
+        self.inner.submit_tx_raw(data).await.map_err(SignerError::Inner)
+    }
+
+    async fn submit_tx_nowait(&self, tx: UnsignedTransaction) -> Result<Claim, Self::Error> {
+        let data = self.finalize(tx).await?;
+        self.inner
+            .submit_tx_raw_nowait(data)
+            .await
+            .map_err(SignerError::Inner)
+    }
+}
+
+/// Errors raised by [`SignerMiddleware`].
+#[derive(Debug, thiserror::Error)]
+pub enum SignerError<E: std::error::Error + 'static> {
+    /// An error from the wrapped middleware.
+    #[error(transparent)]
+    Inner(E),
+
+    /// A wallet failed to produce a nonce or a signature.
+    #[error("wallet error: {0}")]
+    Wallet(anyhow::Error),
+}
+
+impl<E: std::error::Error + 'static> From<E> for SignerError<E> {
+    fn from(e: E) -> Self {
+        Self::Inner(e)
+    }
+}
+
+impl<E: std::error::Error + StaleNonceError + 'static> StaleNonceError for SignerError<E> {
+    fn is_stale_nonce(&self) -> bool {
+        match self {
+            Self::Inner(e) => e.is_stale_nonce(),
+            Self::Wallet(_) => false,
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! The individual gRPC request/response pairs `Client` sends to the oasis-node runtime client
+//! service. Each `Request` impl names the method path to call and the type its response decodes
+//! into; `Client::unary`/`server_streaming` do the rest generically.
+use oasis_runtime_sdk::core::{
+    common::crypto::hash::Hash,
+    common::namespace::Namespace,
+    consensus::roothash::{AnnotatedBlock, Block},
+    transaction::tags::Tag,
+};
+
+/// A single unary or server-streaming request to the runtime client service.
+pub trait Request: cbor::Encode + Send + 'static {
+    /// The decoded response (or, for a streaming request, the decoded type of each streamed
+    /// item).
+    type Response: cbor::Decode + Send + 'static;
+
+    /// The fully qualified gRPC method path to invoke.
+    fn path() -> &'static str;
+
+    /// The CBOR-encodable request body. Every request here is CBOR-encoded as itself.
+    fn body(self) -> Self {
+        self
+    }
+}
+
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct GetChainContextRequest {}
+
+impl Request for GetChainContextRequest {
+    type Response = Vec<u8>;
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/GetChainContext"
+    }
+}
+
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct QueryRequest {
+    pub runtime_id: Namespace,
+    pub round: u64,
+    pub method: String,
+    pub args: cbor::Value,
+}
+
+#[derive(Clone, Debug, cbor::Decode)]
+pub struct QueryResponse {
+    pub data: cbor::Value,
+}
+
+impl Request for QueryRequest {
+    type Response = QueryResponse;
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/Query"
+    }
+}
+
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct GetBlockRequest {
+    pub runtime_id: Namespace,
+    pub round: u64,
+}
+
+impl Request for GetBlockRequest {
+    type Response = Block;
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/GetBlock"
+    }
+}
+
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct GetEventsRequest {
+    pub runtime_id: Namespace,
+    pub round: u64,
+}
+
+impl Request for GetEventsRequest {
+    type Response = Vec<Event>;
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/GetEvents"
+    }
+}
+
+/// The wire form of a single runtime event, as returned by `GetEventsRequest`. It carries the
+/// same hash, key and value as [`Tag`], and `Client` converts each one into a `Tag` field by
+/// field.
+#[derive(Clone, Debug, cbor::Decode)]
+pub struct Event {
+    pub tx_hash: Hash,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl From<Event> for Tag {
+    fn from(event: Event) -> Self {
+        Tag {
+            tx_hash: event.tx_hash,
+            key: event.key,
+            value: event.value,
+        }
+    }
+}
+
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct WatchBlocksRequest {
+    pub runtime_id: Namespace,
+}
+
+impl Request for WatchBlocksRequest {
+    type Response = AnnotatedBlock;
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/WatchBlocks"
+    }
+}
+
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct SubmitTxRequest {
+    pub runtime_id: Namespace,
+    pub data: Vec<u8>,
+}
+
+impl Request for SubmitTxRequest {
+    type Response = Vec<u8>;
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/SubmitTx"
+    }
+}
+
+/// Like [`SubmitTxRequest`], but the node enqueues the transaction into its mempool and responds
+/// immediately instead of blocking until it's included in a block.
+#[derive(Clone, Debug, cbor::Encode)]
+pub struct SubmitTxNoWaitRequest {
+    pub runtime_id: Namespace,
+    pub data: Vec<u8>,
+}
+
+impl Request for SubmitTxNoWaitRequest {
+    type Response = ();
+
+    fn path() -> &'static str {
+        "/oasis-core.RuntimeClient/SubmitTxNoWait"
+    }
+}
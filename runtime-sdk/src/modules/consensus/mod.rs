@@ -8,7 +8,8 @@ use thiserror::Error;
 use oasis_core_runtime::{
     common::versioned::Versioned,
     consensus::{
-        roothash::{Message, StakingMessage},
+        beacon::EpochTime,
+        roothash::{Message, MessageEvent, StakingMessage},
         staking,
         staking::Account as ConsensusAccount,
         state::{staking::ImmutableState as StakingImmutableState, StateError},
@@ -21,6 +22,7 @@ use crate::{
     module::Module as _,
     modules,
     modules::core::{Module as Core, API as _},
+    storage,
     types::{
         address::{Address, SignatureAddressSpec},
         message::MessageEventHookInvocation,
@@ -39,12 +41,20 @@ const MODULE_NAME: &str = "consensus";
 #[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
 pub struct Parameters {
     pub consensus_denomination: token::Denomination,
+
+    /// Maximum total amount the runtime may transfer out of its consensus account (via
+    /// `transfer`) within a single epoch, summed over all recipients, expressed in the consensus
+    /// denomination so the limit is interpreted at the correct decimal scale rather than as a raw
+    /// integer. The limit is not tracked per account, and `withdraw`, `escrow` and
+    /// `reclaim_escrow` are not counted against it. `None` means no limit is enforced.
+    pub max_withdraw_per_epoch: Option<token::BaseUnits>,
 }
 
 impl Default for Parameters {
     fn default() -> Self {
         Self {
             consensus_denomination: token::Denomination::from_str("TEST").unwrap(),
+            max_withdraw_per_epoch: None,
         }
     }
 }
@@ -52,10 +62,83 @@ impl Default for Parameters {
 impl module::Parameters for Parameters {
     type Error = ();
 }
-/// Events emitted by the consensus module (none so far).
+
+/// Name of the hook invoked once a `Transfer` consensus message resolves.
+pub const MESSAGE_RESULT_TRANSFER_HANDLER: &str = "consensus.Transfer";
+
+/// Name of the hook invoked once a `Withdraw` consensus message resolves.
+pub const MESSAGE_RESULT_WITHDRAW_HANDLER: &str = "consensus.Withdraw";
+
+/// Name of the hook invoked once an `AddEscrow` consensus message resolves.
+pub const MESSAGE_RESULT_ESCROW_HANDLER: &str = "consensus.Escrow";
+
+/// Name of the hook invoked once a `ReclaimEscrow` consensus message resolves.
+pub const MESSAGE_RESULT_RECLAIM_ESCROW_HANDLER: &str = "consensus.ReclaimEscrow";
+
+/// Context for the message result handler invoked once a `Transfer` consensus message resolves.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct TransferHookContext {
+    pub to: Address,
+    pub amount: token::BaseUnits,
+}
+
+/// Context for the message result handler invoked once a `Withdraw` consensus message resolves.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct WithdrawHookContext {
+    pub from: Address,
+    pub amount: token::BaseUnits,
+}
+
+/// Context for the message result handler invoked once an `AddEscrow` consensus message resolves.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct EscrowHookContext {
+    pub to: Address,
+    pub amount: token::BaseUnits,
+}
+
+/// Context for the message result handler invoked once a `ReclaimEscrow` consensus message
+/// resolves.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct ReclaimEscrowHookContext {
+    pub from: Address,
+    pub amount: token::BaseUnits,
+}
+
+/// Events emitted by the consensus module, recording whether a consensus operation this runtime
+/// initiated actually succeeded on the consensus layer. Opt in by passing
+/// `MessageEventHookInvocation::new(MODULE_NAME, MESSAGE_RESULT_*_HANDLER, ..HookContext { .. })`
+/// as the hook to `transfer`/`withdraw`/`escrow`/`reclaim_escrow` instead of a caller-owned hook.
 #[derive(Debug, cbor::Encode, oasis_runtime_sdk_macros::Event)]
 #[cbor(untagged)]
-pub enum Event {}
+pub enum Event {
+    /// A `Transfer` consensus message resolved.
+    Transfer {
+        to: Address,
+        amount: token::BaseUnits,
+        ok: bool,
+    },
+
+    /// A `Withdraw` consensus message resolved.
+    Withdraw {
+        from: Address,
+        amount: token::BaseUnits,
+        ok: bool,
+    },
+
+    /// An `AddEscrow` consensus message resolved.
+    Escrow {
+        to: Address,
+        amount: token::BaseUnits,
+        ok: bool,
+    },
+
+    /// A `ReclaimEscrow` consensus message resolved.
+    ReclaimEscrow {
+        from: Address,
+        amount: token::BaseUnits,
+        ok: bool,
+    },
+}
 
 /// Genesis state for the consensus module.
 #[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
@@ -84,8 +167,30 @@ pub enum Error {
     #[error("consensus incompatible signer")]
     #[sdk_error(code = 4)]
     ConsensusIncompatibleSigner,
+
+    #[error("withdraw limit exceeded")]
+    #[sdk_error(code = 5)]
+    WithdrawLimitExceeded,
+}
+
+/// Accumulator tracking how much has been withdrawn from the runtime's consensus account this
+/// epoch in total, enforced against `Parameters::max_withdraw_per_epoch`. Tracked globally rather
+/// than per destination address: `transfer` is a general API, and a limit that only bounded each
+/// recipient individually would do nothing to bound the runtime's aggregate consensus-layer
+/// drain (an attacker could simply spread withdrawals across many recipients). Persisted under
+/// `WITHDRAW_ACCUMULATOR_KEY` and reset whenever `BlockHandler::end_block` observes the epoch has
+/// advanced.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+struct WithdrawAccumulator {
+    /// The epoch `withdrawn` applies to.
+    epoch: EpochTime,
+    /// Total amount withdrawn so far this epoch, in the consensus denomination.
+    withdrawn: u128,
 }
 
+/// Storage key under which the withdraw accumulator is persisted.
+const WITHDRAW_ACCUMULATOR_KEY: &[u8] = &[0x01];
+
 /// Interface that can be called from other modules.
 pub trait API {
     /// Transfer an amount from the runtime account.
@@ -143,6 +248,126 @@ impl Module {
 
         Ok(())
     }
+
+    /// Loads the persisted withdraw accumulator, ignoring (rather than propagating) a stale
+    /// epoch — callers that care about staleness reset it themselves (`charge_withdraw_limit`
+    /// and `end_block`).
+    fn load_withdraw_accumulator<C: Context>(ctx: &mut C) -> WithdrawAccumulator {
+        ctx.runtime_state()
+            .get(WITHDRAW_ACCUMULATOR_KEY)
+            .expect("storage get must succeed")
+            .map(|data| {
+                cbor::from_slice(&data).expect("withdraw accumulator must deserialize correctly")
+            })
+            .unwrap_or_default()
+    }
+
+    fn store_withdraw_accumulator<C: Context>(ctx: &mut C, acc: &WithdrawAccumulator) {
+        ctx.runtime_state()
+            .insert(WITHDRAW_ACCUMULATOR_KEY, &cbor::to_vec(acc.clone()))
+            .expect("storage insert must succeed");
+    }
+
+    /// Checks `amount` (in the consensus denomination) against the runtime's remaining aggregate
+    /// withdrawal allowance for the current epoch, and if it fits, records it against the
+    /// accumulator. Applies to `transfer`, since that's the message that actually drains the
+    /// runtime's consensus-layer balance. A no-op if `Parameters::max_withdraw_per_epoch` isn't
+    /// set; returns `Error::InvalidDenomination` if it's set in anything other than the consensus
+    /// denomination, since the limit would otherwise be silently compared against the wrong
+    /// scale.
+    fn charge_withdraw_limit<C: TxContext>(
+        ctx: &mut C,
+        consensus_amount: u128,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+        let limit = match &params.max_withdraw_per_epoch {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        if limit.denomination() != &params.consensus_denomination {
+            return Err(Error::InvalidDenomination);
+        }
+        let limit = limit.amount();
+
+        let mut acc = Self::load_withdraw_accumulator(ctx);
+        if acc.epoch != ctx.epoch() {
+            acc = WithdrawAccumulator {
+                epoch: ctx.epoch(),
+                withdrawn: 0,
+            };
+        }
+
+        let new_total = acc
+            .withdrawn
+            .checked_add(consensus_amount)
+            .ok_or(Error::WithdrawLimitExceeded)?;
+        if new_total > limit {
+            return Err(Error::WithdrawLimitExceeded);
+        }
+
+        if !ctx.is_check_only() {
+            acc.withdrawn = new_total;
+            Self::store_withdraw_accumulator(ctx, &acc);
+        }
+
+        Ok(())
+    }
+
+    /// Handles the result of a `Transfer` consensus message hooked to
+    /// `MESSAGE_RESULT_TRANSFER_HANDLER`.
+    pub fn message_result_transfer<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        h_ctx: TransferHookContext,
+    ) {
+        ctx.emit_event(Event::Transfer {
+            to: h_ctx.to,
+            amount: h_ctx.amount,
+            ok: me.is_success(),
+        });
+    }
+
+    /// Handles the result of a `Withdraw` consensus message hooked to
+    /// `MESSAGE_RESULT_WITHDRAW_HANDLER`.
+    pub fn message_result_withdraw<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        h_ctx: WithdrawHookContext,
+    ) {
+        ctx.emit_event(Event::Withdraw {
+            from: h_ctx.from,
+            amount: h_ctx.amount,
+            ok: me.is_success(),
+        });
+    }
+
+    /// Handles the result of an `AddEscrow` consensus message hooked to
+    /// `MESSAGE_RESULT_ESCROW_HANDLER`.
+    pub fn message_result_escrow<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        h_ctx: EscrowHookContext,
+    ) {
+        ctx.emit_event(Event::Escrow {
+            to: h_ctx.to,
+            amount: h_ctx.amount,
+            ok: me.is_success(),
+        });
+    }
+
+    /// Handles the result of a `ReclaimEscrow` consensus message hooked to
+    /// `MESSAGE_RESULT_RECLAIM_ESCROW_HANDLER`.
+    pub fn message_result_reclaim_escrow<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        h_ctx: ReclaimEscrowHookContext,
+    ) {
+        ctx.emit_event(Event::ReclaimEscrow {
+            from: h_ctx.from,
+            amount: h_ctx.amount,
+            ok: me.is_success(),
+        });
+    }
 }
 
 impl API for Module {
@@ -153,6 +378,7 @@ impl API for Module {
         hook: MessageEventHookInvocation,
     ) -> Result<(), Error> {
         Self::ensure_consensus_denomination(ctx, amount.denomination())?;
+        Self::charge_withdraw_limit(ctx, amount.amount())?;
 
         Core::add_weight(ctx, TransactionWeight::ConsensusMessages, 1)?;
 
@@ -314,6 +540,21 @@ impl module::MigrationHandler for Module {
 
 impl module::AuthHandler for Module {}
 
-impl module::BlockHandler for Module {}
+impl module::BlockHandler for Module {
+    fn end_block<C: Context>(ctx: &mut C) {
+        let acc = Self::load_withdraw_accumulator(ctx);
+        if acc.epoch != ctx.epoch() {
+            // The epoch has advanced since the accumulator was last touched: the runtime's
+            // aggregate allowance is fresh again.
+            Self::store_withdraw_accumulator(
+                ctx,
+                &WithdrawAccumulator {
+                    epoch: ctx.epoch(),
+                    withdrawn: 0,
+                },
+            );
+        }
+    }
+}
 
 impl module::InvariantHandler for Module {}
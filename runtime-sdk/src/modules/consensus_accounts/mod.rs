@@ -0,0 +1,349 @@
+//! Consensus accounts module.
+//!
+//! This module allows deposits and withdrawals between the runtime's own account on the
+//! consensus layer and accounts within the runtime. A deposit is credited to the runtime account
+//! once the consensus layer confirms it; a withdrawal is debited from the runtime account up
+//! front and refunded if the consensus layer rejects it.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    marker::PhantomData,
+};
+
+use thiserror::Error;
+
+use oasis_core_runtime::consensus::roothash::MessageEvent;
+
+use crate::{
+    context::{Context, TxContext},
+    module,
+    module::Module as _,
+    modules,
+    modules::{accounts::API as _, consensus::API as _},
+    types::{address::Address, message::MessageEventHookInvocation, token, transaction::AuthInfo},
+};
+
+pub mod types;
+#[cfg(test)]
+mod test;
+
+/// Unique module name.
+const MODULE_NAME: &str = "consensus_accounts";
+
+/// Name of the hook invoked once a `Withdraw` consensus message (backing a `Deposit` call)
+/// resolves.
+pub const CONSENSUS_WITHDRAW_HANDLER: &str = "consensus_accounts.Withdraw";
+
+/// Name of the hook invoked once a `Transfer` consensus message (backing a `Withdraw` call)
+/// resolves.
+pub const CONSENSUS_TRANSFER_HANDLER: &str = "consensus_accounts.Transfer";
+
+#[derive(Error, Debug, oasis_runtime_sdk_macros::Error)]
+pub enum Error {
+    #[error("invalid argument")]
+    #[sdk_error(code = 1)]
+    InvalidArgument,
+
+    #[error("consensus: {0}")]
+    #[sdk_error(transparent)]
+    Consensus(#[from] modules::consensus::Error),
+
+    #[error("accounts: {0}")]
+    #[sdk_error(transparent)]
+    Accounts(#[from] modules::accounts::Error),
+
+    #[error("core: {0}")]
+    #[sdk_error(transparent)]
+    Core(#[from] modules::core::Error),
+
+    #[error("amount not representable in the runtime denomination")]
+    #[sdk_error(code = 2)]
+    AmountNotRepresentable,
+
+    #[error("withdrawn amount is not a multiple of the consensus scaling factor")]
+    #[sdk_error(code = 3)]
+    AmountNotDivisible,
+
+    #[error("insufficient balance")]
+    #[sdk_error(code = 4)]
+    InsufficientBalance,
+
+    #[error("amount is smaller than the configured minimum")]
+    #[sdk_error(code = 5)]
+    AmountTooSmall,
+
+    #[error("amount is larger than the configured maximum")]
+    #[sdk_error(code = 6)]
+    AmountTooLarge,
+
+    #[error("denomination is not allowed to be deposited or withdrawn")]
+    #[sdk_error(code = 7)]
+    DenominationNotAllowed,
+}
+
+/// Events emitted by the consensus accounts module (none so far).
+#[derive(Debug, cbor::Encode, oasis_runtime_sdk_macros::Event)]
+#[cbor(untagged)]
+pub enum Event {}
+
+/// Parameters for the consensus accounts module.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Parameters {
+    /// Allow-list of denominations that may be deposited/withdrawn across the consensus bridge.
+    /// `None` means every denomination is allowed.
+    pub allowed_denominations: Option<BTreeSet<token::Denomination>>,
+
+    /// Per-denomination minimum deposit/withdraw amount. A denomination absent from this map has
+    /// no configured minimum.
+    pub min_amounts: BTreeMap<token::Denomination, u128>,
+
+    /// Per-denomination maximum deposit/withdraw amount. A denomination absent from this map has
+    /// no configured maximum.
+    pub max_amounts: BTreeMap<token::Denomination, u128>,
+}
+
+impl module::Parameters for Parameters {
+    type Error = ();
+}
+
+/// Genesis state for the consensus accounts module.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Genesis {
+    pub parameters: Parameters,
+}
+
+/// Module configuration, supplied by the concrete runtime.
+pub trait Config {
+    /// `runtime_decimals - consensus_decimals`, i.e. how many more decimals of precision the
+    /// runtime denomination carries than the consensus layer's native denomination. Used to
+    /// derive the scaling factor `F = 10^AMOUNT_DECIMALS_EXPONENT` applied between consensus
+    /// amounts and runtime amounts on deposit/withdraw.
+    const AMOUNT_DECIMALS_EXPONENT: u8 = 0;
+}
+
+/// Default configuration that applies no scaling, suitable for runtimes whose denomination
+/// matches the consensus layer's decimal count.
+pub struct DefaultConfig;
+
+impl Config for DefaultConfig {}
+
+pub struct Module<
+    Accounts: modules::accounts::API,
+    Consensus: modules::consensus::API,
+    Cfg: Config = DefaultConfig,
+> {
+    _accounts: PhantomData<Accounts>,
+    _consensus: PhantomData<Consensus>,
+    _cfg: PhantomData<Cfg>,
+}
+
+impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API, Cfg: Config>
+    Module<Accounts, Consensus, Cfg>
+{
+    /// The consensus-to-runtime amount scaling factor, `10^AMOUNT_DECIMALS_EXPONENT`.
+    fn scaling_factor() -> u128 {
+        10u128.pow(Cfg::AMOUNT_DECIMALS_EXPONENT as u32)
+    }
+
+    /// Scale a consensus-denominated amount up into the runtime's own denomination.
+    fn scale_up(amount: u128) -> Result<u128, Error> {
+        amount
+            .checked_mul(Self::scaling_factor())
+            .ok_or(Error::AmountNotRepresentable)
+    }
+
+    /// Scale a runtime-denominated amount back down into the consensus layer's denomination.
+    /// Returns an error if `amount` is not an exact multiple of the scaling factor, as that
+    /// would otherwise silently lose precision when crossing back into the consensus layer.
+    fn scale_down(amount: u128) -> Result<u128, Error> {
+        let factor = Self::scaling_factor();
+        if amount % factor != 0 {
+            return Err(Error::AmountNotDivisible);
+        }
+        Ok(amount / factor)
+    }
+
+    /// Checks `amount` (in the consensus denomination) of `denomination` against the configured
+    /// allow-list and min/max bounds. Callers must always pass the consensus-denominated amount,
+    /// even on the withdraw path where the body amount is runtime-denominated, so that a given
+    /// limit means the same real value regardless of direction.
+    fn ensure_within_policy<C: Context>(
+        ctx: &mut C,
+        denomination: &token::Denomination,
+        amount: u128,
+    ) -> Result<(), Error> {
+        let params = Self::params(ctx.runtime_state());
+
+        if let Some(allowed) = &params.allowed_denominations {
+            if !allowed.contains(denomination) {
+                return Err(Error::DenominationNotAllowed);
+            }
+        }
+        if let Some(min) = params.min_amounts.get(denomination) {
+            if amount < *min {
+                return Err(Error::AmountTooSmall);
+            }
+        }
+        if let Some(max) = params.max_amounts.get(denomination) {
+            if amount > *max {
+                return Err(Error::AmountTooLarge);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deposit from the given tx signer's consensus account into its runtime account.
+    pub fn tx_deposit<C: TxContext>(ctx: &mut C, body: types::Deposit) -> Result<(), Error> {
+        Consensus::ensure_compatible_tx_signer(ctx)?;
+        Self::ensure_within_policy(ctx, body.amount.denomination(), body.amount.amount())?;
+
+        let address = Address::from_sigspec(&ctx.tx_auth_info().signer_info[0].address_spec);
+        let scaled_amount = Self::scale_up(body.amount.amount())?;
+
+        let hook = MessageEventHookInvocation::new(
+            MODULE_NAME,
+            CONSENSUS_WITHDRAW_HANDLER,
+            types::ConsensusWithdrawContext {
+                address,
+                amount: token::BaseUnits::new(scaled_amount, body.amount.denomination().clone()),
+            },
+        );
+        Consensus::withdraw(ctx, address, &body.amount, hook)?;
+
+        Ok(())
+    }
+
+    /// Withdraw from the given tx signer's runtime account into its consensus account.
+    pub fn tx_withdraw<C: TxContext>(ctx: &mut C, body: types::Withdraw) -> Result<(), Error> {
+        Consensus::ensure_compatible_tx_signer(ctx)?;
+
+        let address = Address::from_sigspec(&ctx.tx_auth_info().signer_info[0].address_spec);
+        let consensus_amount = Self::scale_down(body.amount.amount())?;
+        Self::ensure_within_policy(ctx, body.amount.denomination(), consensus_amount)?;
+
+        // Reject up front if the signer clearly doesn't have the funds, then debit the runtime
+        // account immediately rather than waiting for the consensus layer to confirm the
+        // `Transfer` in `message_result_transfer`: that confirmation only lands in a later round,
+        // so leaving the balance unreserved until then would let two withdrawals from the same
+        // account in the same block both pass this check and both emit a transfer. If the
+        // consensus-level transfer ends up failing, `message_result_transfer` refunds the debit.
+        let balances = Accounts::get_balances(ctx.runtime_state(), address)?;
+        let available = balances
+            .balances
+            .get(body.amount.denomination())
+            .copied()
+            .unwrap_or_default();
+        if available < body.amount.amount() {
+            return Err(Error::InsufficientBalance);
+        }
+        Accounts::sub_amount(ctx.runtime_state(), address, &body.amount)?;
+
+        let hook = MessageEventHookInvocation::new(
+            MODULE_NAME,
+            CONSENSUS_TRANSFER_HANDLER,
+            types::ConsensusTransferContext {
+                address,
+                amount: body.amount.clone(),
+            },
+        );
+        Consensus::transfer(
+            ctx,
+            address,
+            &token::BaseUnits::new(consensus_amount, body.amount.denomination().clone()),
+            hook,
+        )?;
+
+        Ok(())
+    }
+
+    /// Handles the result of a `Withdraw` consensus message previously emitted by `tx_deposit`.
+    pub fn message_result_withdraw<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        h_ctx: types::ConsensusWithdrawContext,
+    ) {
+        if !me.is_success() {
+            // The consensus-level withdraw failed, so nothing was ever moved into the runtime
+            // account and there is nothing to undo.
+            return;
+        }
+
+        // Crediting can only fail on `u128` overflow. Message-result hooks have no way to report
+        // an error back to the caller, so rather than panicking and aborting the whole batch over
+        // it, the credit is simply dropped.
+        let _ = Accounts::add_amount(ctx.runtime_state(), h_ctx.address, &h_ctx.amount);
+    }
+
+    /// Handles the result of a `Transfer` consensus message previously emitted by `tx_withdraw`.
+    pub fn message_result_transfer<C: Context>(
+        ctx: &mut C,
+        me: MessageEvent,
+        h_ctx: types::ConsensusTransferContext,
+    ) {
+        if me.is_success() {
+            // The runtime account was already debited up front in `tx_withdraw`; nothing further
+            // to do.
+            return;
+        }
+
+        // The consensus-level transfer failed, so refund the amount debited up front in
+        // `tx_withdraw`. The refund can only fail on `u128` overflow; message-result hooks have
+        // no way to report an error back to the caller, so rather than panicking and aborting the
+        // whole batch over it, the refund is simply dropped.
+        let _ = Accounts::add_amount(ctx.runtime_state(), h_ctx.address, &h_ctx.amount);
+    }
+
+    /// Prefetch state accessed by a call to the given method.
+    pub fn prefetch(
+        prefixes: &mut BTreeSet<Vec<u8>>,
+        method: &str,
+        _body: cbor::Value,
+        auth_info: &AuthInfo,
+    ) -> Option<Result<(), Error>> {
+        match method {
+            "consensus.Deposit" => Some(Ok(())),
+            "consensus.Withdraw" => {
+                let address = Address::from_sigspec(&auth_info.signer_info[0].address_spec);
+                Accounts::prefetch_balances(prefixes, address);
+                Some(Ok(()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API, Cfg: Config>
+    module::Module for Module<Accounts, Consensus, Cfg>
+{
+    const NAME: &'static str = MODULE_NAME;
+    const VERSION: u32 = 1;
+    type Error = Error;
+    type Event = Event;
+    type Parameters = Parameters;
+}
+
+impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API, Cfg: Config>
+    module::MethodHandler for Module<Accounts, Consensus, Cfg>
+{
+}
+
+impl<Accounts: modules::accounts::API, Consensus: modules::consensus::API, Cfg: Config>
+    module::MigrationHandler for Module<Accounts, Consensus, Cfg>
+{
+    type Genesis = Genesis;
+
+    fn init_or_migrate<C: Context>(
+        ctx: &mut C,
+        meta: &mut modules::core::types::Metadata,
+        genesis: Self::Genesis,
+    ) -> bool {
+        let version = meta.versions.get(Self::NAME).copied().unwrap_or_default();
+        if version == 0 {
+            Self::set_params(ctx.runtime_state(), genesis.parameters);
+            meta.versions.insert(Self::NAME.to_owned(), Self::VERSION);
+            return true;
+        }
+
+        false
+    }
+}
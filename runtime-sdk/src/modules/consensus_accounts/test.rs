@@ -1,11 +1,14 @@
-use std::{collections::BTreeMap, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
 
 use anyhow::anyhow;
 
 use oasis_core_runtime::{
     common::versioned::Versioned,
     consensus::{
-        roothash::{Message, StakingMessage},
+        roothash::{Message, MessageEvent, StakingMessage},
         staking,
     },
 };
@@ -55,6 +58,8 @@ fn test_api_deposit_invalid_denomination() {
 
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Deposit".to_owned(),
@@ -97,6 +102,8 @@ fn test_api_deposit() {
 
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Deposit".to_owned(),
@@ -150,6 +157,162 @@ fn test_api_deposit() {
     });
 }
 
+#[test]
+fn test_api_deposit_denomination_not_allowed() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    let genesis = Genesis {
+        parameters: Parameters {
+            allowed_denominations: Some(BTreeSet::from([Denomination::NATIVE])),
+            ..Default::default()
+        },
+    };
+
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        not_before: None,
+        not_after: None,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Deposit".to_owned(),
+            body: cbor::to_value(Deposit {
+                amount: BaseUnits::new(1_000, denom),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+        },
+    };
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        assert!(matches!(
+            Module::<Accounts, Consensus>::tx_deposit(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            ),
+            Err(Error::DenominationNotAllowed)
+        ));
+    });
+}
+
+#[test]
+fn test_api_deposit_amount_too_small() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    let genesis = Genesis {
+        parameters: Parameters {
+            min_amounts: BTreeMap::from([(denom.clone(), 10_000)]),
+            ..Default::default()
+        },
+    };
+
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        not_before: None,
+        not_after: None,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Deposit".to_owned(),
+            body: cbor::to_value(Deposit {
+                amount: BaseUnits::new(1_000, denom),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+        },
+    };
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        assert!(matches!(
+            Module::<Accounts, Consensus>::tx_deposit(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            ),
+            Err(Error::AmountTooSmall)
+        ));
+    });
+}
+
+#[test]
+fn test_api_deposit_amount_too_large() {
+    let denom: Denomination = Denomination::from_str("TEST").unwrap();
+    let mut mock = mock::Mock::default();
+    let mut ctx = mock.create_ctx();
+    let mut meta = Metadata {
+        ..Default::default()
+    };
+    let genesis = Genesis {
+        parameters: Parameters {
+            max_amounts: BTreeMap::from([(denom.clone(), 10_000)]),
+            ..Default::default()
+        },
+    };
+
+    Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, genesis);
+
+    let tx = transaction::Transaction {
+        version: 1,
+        not_before: None,
+        not_after: None,
+        call: transaction::Call {
+            format: transaction::CallFormat::Plain,
+            method: "consensus.Deposit".to_owned(),
+            body: cbor::to_value(Deposit {
+                amount: BaseUnits::new(20_000, denom),
+            }),
+        },
+        auth_info: transaction::AuthInfo {
+            signer_info: vec![transaction::SignerInfo::new_sigspec(
+                keys::alice::sigspec(),
+                0,
+            )],
+            fee: transaction::Fee {
+                amount: Default::default(),
+                gas: 1000,
+                consensus_messages: 1,
+            },
+        },
+    };
+
+    ctx.with_tx(0, tx, |mut tx_ctx, call| {
+        assert!(matches!(
+            Module::<Accounts, Consensus>::tx_deposit(
+                &mut tx_ctx,
+                cbor::from_value(call.body).unwrap(),
+            ),
+            Err(Error::AmountTooLarge)
+        ));
+    });
+}
+
 #[test]
 fn test_api_withdraw_invalid_denomination() {
     let mut mock = mock::Mock::default();
@@ -163,6 +326,8 @@ fn test_api_withdraw_invalid_denomination() {
 
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Withdraw".to_owned(),
@@ -205,6 +370,8 @@ fn test_api_withdraw_insufficient_balance() {
 
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Withdraw".to_owned(),
@@ -269,6 +436,8 @@ fn test_api_withdraw() {
 
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Withdraw".to_owned(),
@@ -355,17 +524,38 @@ fn test_consensus_transfer_handler() {
     );
     Module::<Accounts, Consensus>::init_or_migrate(&mut ctx, &mut meta, Default::default());
 
-    // Simulate successful event.
-    let me = Default::default();
+    // Simulate a successful event: `tx_withdraw` already debited the runtime account up front,
+    // so this is a no-op and the balance should be unchanged.
+    let me: MessageEvent = Default::default();
+    let h_ctx = types::ConsensusTransferContext {
+        address: keys::alice::address(),
+        amount: BaseUnits::new(999_999, denom.clone()),
+    };
+    Module::<Accounts, Consensus>::message_result_transfer(&mut ctx, me, h_ctx);
+
+    let bals = Accounts::get_balances(ctx.runtime_state(), keys::alice::address()).unwrap();
+    assert_eq!(
+        bals.balances[&denom], 1_000_000,
+        "successful transfer should not touch the already-debited balance"
+    );
+
+    // Simulate a failed event: the debit made in `tx_withdraw` must be refunded.
+    let me = MessageEvent {
+        code: 1,
+        ..Default::default()
+    };
     let h_ctx = types::ConsensusTransferContext {
         address: keys::alice::address(),
         amount: BaseUnits::new(999_999, denom.clone()),
     };
     Module::<Accounts, Consensus>::message_result_transfer(&mut ctx, me, h_ctx);
 
-    // Ensure runtime balance is updated.
     let bals = Accounts::get_balances(ctx.runtime_state(), keys::alice::address()).unwrap();
-    assert_eq!(bals.balances[&denom], 1, "alice balance transferred out")
+    assert_eq!(
+        bals.balances[&denom],
+        1_000_000 + 999_999,
+        "failed transfer should refund the debited amount"
+    )
 }
 
 #[test]
@@ -437,6 +627,8 @@ fn test_prefetch() {
     // Test withdraw.
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Withdraw".to_owned(),
@@ -465,6 +657,8 @@ fn test_prefetch() {
     // Test deposit.
     let tx = transaction::Transaction {
         version: 1,
+        not_before: None,
+        not_after: None,
         call: transaction::Call {
             format: transaction::CallFormat::Plain,
             method: "consensus.Deposit".to_owned(),
@@ -494,3 +688,36 @@ fn test_prefetch() {
         );
     });
 }
+
+/// A [`Config`] with a non-zero scaling exponent, so `scale_up`/`scale_down` actually exercise
+/// their scaling logic instead of the no-op factor of 1 that `DefaultConfig` gives every other
+/// test in this file.
+struct ScaledConfig;
+
+impl Config for ScaledConfig {
+    const AMOUNT_DECIMALS_EXPONENT: u8 = 3;
+}
+
+type ScaledModule = Module<Accounts, Consensus, ScaledConfig>;
+
+#[test]
+fn test_scale_up_and_down() {
+    assert_eq!(ScaledModule::scale_up(5).unwrap(), 5_000);
+    assert_eq!(ScaledModule::scale_down(5_000).unwrap(), 5);
+}
+
+#[test]
+fn test_scale_up_not_representable() {
+    assert!(matches!(
+        ScaledModule::scale_up(u128::MAX),
+        Err(Error::AmountNotRepresentable)
+    ));
+}
+
+#[test]
+fn test_scale_down_not_divisible() {
+    assert!(matches!(
+        ScaledModule::scale_down(5_001),
+        Err(Error::AmountNotDivisible)
+    ));
+}
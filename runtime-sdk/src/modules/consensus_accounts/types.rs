@@ -0,0 +1,29 @@
+use crate::types::{address::Address, token};
+
+/// Deposit into runtime call.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Deposit {
+    pub amount: token::BaseUnits,
+}
+
+/// Withdraw out of runtime call.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Withdraw {
+    pub amount: token::BaseUnits,
+}
+
+/// Context for the message result handler invoked once a `Withdraw` consensus message resolves.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct ConsensusWithdrawContext {
+    pub address: Address,
+    /// Amount credited to `address`, expressed in runtime denomination units.
+    pub amount: token::BaseUnits,
+}
+
+/// Context for the message result handler invoked once a `Transfer` consensus message resolves.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct ConsensusTransferContext {
+    pub address: Address,
+    /// Amount debited from `address`, expressed in runtime denomination units.
+    pub amount: token::BaseUnits,
+}
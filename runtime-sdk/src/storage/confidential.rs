@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
+
 use anyhow::{anyhow, Error};
 use thiserror::Error as _;
 
 use crate::{
     core::{
-        common::crypto::mrae::deoxysii,
+        common::crypto::{hash::Hash, mrae::deoxysii},
         storage::mkvs,
     },
     context::Context,
@@ -13,87 +15,244 @@ use crate::{
     storage::Store,
 };
 
+/// The nonce used to seal map keys.
+///
+/// Key encryption is intentionally deterministic (every key always seals to the same
+/// ciphertext), since the ciphertext doubles as the lookup index in the inner store; this is
+/// equivalent to a searchable-encryption scheme and does not provide semantic security for the
+/// keys themselves. The values stored alongside them are what per-key nonces (see
+/// `derive_value_nonce`) protect. The index key is intentionally exempt from generation rotation
+/// (see `ConfidentialStore::generation_kid`), since rotating it would change where existing
+/// entries live and make them unreachable.
+const KEY_NONCE: [u8; deoxysii::NONCE_SIZE] = [0; deoxysii::NONCE_SIZE];
+
+/// Keys for a single key generation, along with the secret key bytes used as PRF input when
+/// deriving per-key value nonces.
+struct GenerationKeys {
+    keypair: KeyPair,
+    key: Vec<u8>,
+}
+
 /// A key-value store that encrypts all content with DeoxysII.
+///
+/// Values are sealed under a rotatable keymanager key generation: `rotate` advances the
+/// generation used for new writes while keeping older generations' keys cached so that entries
+/// written under them stay readable. Each stored value is tagged with the one-byte generation it
+/// was sealed under, followed by an 8-byte per-key write counter (see `derive_value_nonce`);
+/// entries are migrated to the current generation the next time they're written, not eagerly on
+/// rotation.
 pub struct ConfidentialStore<S: Store> {
     inner: S,
-    keypair: KeyPair,
-    key: Vec<u8>,
+    /// The id this store was constructed with. Used, unmodified, to derive the (non-rotating)
+    /// index key, and as the basis from which per-generation value key ids are derived.
+    base_kid: KeyPairId,
+    /// Keys used to seal/unseal the map keys (`ekey`). Fixed for the lifetime of the store.
+    index_keys: GenerationKeys,
+    /// The generation new values are sealed under.
+    generation: u8,
+    /// Cache of value keys for generations seen so far, keyed by generation.
+    value_keys: BTreeMap<u8, GenerationKeys>,
 }
 
 impl<S: Store> ConfidentialStore<S> {
     /// Create a new confidential store with the given keymanager key id.
     pub fn new_with_id<C: Context>(ctx: &C, inner: S, kid: KeyPairId) -> Result<Self, Error> {
-        let kmgr = ctx.key_manager().ok_or_else(|| { anyhow!("confidential transactions not available") })?;
+        let index_keys = Self::fetch_keys(ctx, kid)?;
+        let mut store = ConfidentialStore {
+            inner,
+            base_kid: kid,
+            index_keys,
+            generation: 0,
+            value_keys: BTreeMap::new(),
+        };
+        store.load_generation(ctx, 0)?;
+        Ok(store)
+    }
+
+    /// Rotates to a new keymanager key generation for newly written values. Entries written
+    /// under older generations remain readable via their recorded generation byte, and are
+    /// migrated forward to the current generation the next time they're written.
+    pub fn rotate<C: Context>(&mut self, ctx: &C) -> Result<(), Error> {
+        let next = self
+            .generation
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("key generation exhausted"))?;
+        self.load_generation(ctx, next)?;
+        self.generation = next;
+        Ok(())
+    }
+
+    /// Derives the keymanager key id used to seal values written under `generation`.
+    fn generation_kid(&self, generation: u8) -> KeyPairId {
+        Hash::digest_bytes_list(&[self.base_kid.as_ref(), &[generation]])
+    }
+
+    fn fetch_keys<C: Context>(ctx: &C, kid: KeyPairId) -> Result<GenerationKeys, Error> {
+        let kmgr = ctx
+            .key_manager()
+            .ok_or_else(|| anyhow!("confidential transactions not available"))?;
         let keypair = kmgr.get_or_create_keys(kid)?;
         let key: Vec<u8> = keypair.input_keypair.sk.0.to_vec();
-        Ok(ConfidentialStore {
-            inner: inner,
-            keypair: keypair,
-            key: key,
-        })
+        Ok(GenerationKeys { keypair, key })
+    }
+
+    /// Fetches and caches the value keys for `generation`, if not already cached.
+    fn load_generation<C: Context>(&mut self, ctx: &C, generation: u8) -> Result<(), Error> {
+        if self.value_keys.contains_key(&generation) {
+            return Ok(());
+        }
+        let kid = self.generation_kid(generation);
+        let keys = Self::fetch_keys(ctx, kid)?;
+        self.value_keys.insert(generation, keys);
+        Ok(())
+    }
+
+    /// Returns the cached value keys for `generation`.
+    fn value_keys(&self, generation: u8) -> Result<&GenerationKeys, Error> {
+        self.value_keys
+            .get(&generation)
+            .ok_or_else(|| anyhow!("no cached keys for confidential store generation {}", generation))
     }
 
-    fn encode_key(&self, key: &[u8]) -> Result<([u8; deoxysii::NONCE_SIZE], Vec<u8>), Error> {
-        let mut nonce: [u8; deoxysii::NONCE_SIZE] = [0; deoxysii::NONCE_SIZE];
-        let result = deoxysii::box_seal(
-            &nonce,
+    /// Derives the nonce used to seal the value stored under `key` at write `counter` (see
+    /// `insert`) in the given generation. The nonce is a function of the plaintext key, that
+    /// generation's secret key material, and `counter`, so distinct keys never share a value
+    /// nonce even though every key is itself sealed under the fixed `KEY_NONCE`, and overwriting
+    /// the same key with a new value never reuses a nonce either: `counter` is bumped on every
+    /// write to that key, which is what `box_seal` needs to stay safe against two-time-pad
+    /// attacks when the same key material is used more than once.
+    fn derive_value_nonce(
+        &self,
+        gk: &GenerationKeys,
+        key: &[u8],
+        counter: u64,
+    ) -> [u8; deoxysii::NONCE_SIZE] {
+        let digest = Hash::digest_bytes_list(&[&gk.key, key, &counter.to_le_bytes()]);
+        let mut nonce = [0u8; deoxysii::NONCE_SIZE];
+        nonce.copy_from_slice(&digest.as_ref()[..deoxysii::NONCE_SIZE]);
+        nonce
+    }
+
+    fn encode_key(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        deoxysii::box_seal(
+            &KEY_NONCE,
             key.to_vec(),
             Vec::new(),
-            &self.keypair.input_keypair.pk.0,
-            &self.keypair.input_keypair.sk.0,
-        )?;
-        Ok((nonce, result))
+            &self.index_keys.keypair.input_keypair.pk.0,
+            &self.index_keys.keypair.input_keypair.sk.0,
+        )
     }
 
-    fn encode_value(&self, nonce: &[u8; deoxysii::NONCE_SIZE], value: &[u8]) -> Result<Vec<u8>, Error> {
+    fn decode_key(&self, ekey: &[u8]) -> Result<Vec<u8>, Error> {
+        deoxysii::box_open(
+            &KEY_NONCE,
+            ekey.to_vec(),
+            Vec::new(),
+            &self.index_keys.keypair.input_keypair.pk.0,
+            &self.index_keys.keypair.input_keypair.sk.0,
+        )
+    }
+
+    fn encode_value(
+        &self,
+        gk: &GenerationKeys,
+        nonce: &[u8; deoxysii::NONCE_SIZE],
+        value: &[u8],
+    ) -> Result<Vec<u8>, Error> {
         deoxysii::box_seal(
             nonce,
             value.to_vec(),
             Vec::new(),
-            &self.keypair.input_keypair.pk.0,
-            &self.keypair.input_keypair.sk.0,
+            &gk.keypair.input_keypair.pk.0,
+            &gk.keypair.input_keypair.sk.0,
         )
     }
 
-    fn decode_value(&self, nonce: &[u8; deoxysii::NONCE_SIZE], value: &[u8]) -> Result<Vec<u8>, Error> {
+    fn decode_value(
+        &self,
+        gk: &GenerationKeys,
+        nonce: &[u8; deoxysii::NONCE_SIZE],
+        value: &[u8],
+    ) -> Result<Vec<u8>, Error> {
         deoxysii::box_open(
             nonce,
             value.to_vec(),
             Vec::new(),
-            &self.keypair.input_keypair.pk.0,
-            &self.keypair.input_keypair.sk.0,
+            &gk.keypair.input_keypair.pk.0,
+            &gk.keypair.input_keypair.sk.0,
         )
     }
 }
 
+/// Splits a stored entry into its leading one-byte generation tag, its 8-byte per-key write
+/// counter (see `ConfidentialStore::derive_value_nonce`), and the sealed value.
+fn split_entry(tagged: &[u8]) -> Result<(u8, u64, &[u8]), Error> {
+    if tagged.len() < 1 + 8 {
+        return Err(anyhow!("truncated confidential store entry"));
+    }
+    let generation = tagged[0];
+    let counter = u64::from_le_bytes(tagged[1..9].try_into().unwrap());
+    Ok((generation, counter, &tagged[9..]))
+}
+
 impl<S: Store> Store for ConfidentialStore<S> {
-    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        let (nonce, ekey) = self.encode_key(key).expect("error encrypting key");
-        match self.inner.get(&ekey) {
-            None => None,
-            Some(evalue) => {
-                let value = self.decode_value(&nonce, &evalue).expect("error decrypting value");
-                Some(value)
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let ekey = self.encode_key(key)?;
+        match self.inner.get(&ekey)? {
+            None => Ok(None),
+            Some(tagged) => {
+                let (generation, counter, evalue) = split_entry(&tagged)?;
+                let gk = self.value_keys(generation)?;
+                let nonce = self.derive_value_nonce(gk, key, counter);
+                let value = self.decode_value(gk, &nonce, evalue)?;
+                Ok(Some(value))
             }
         }
     }
 
-    fn insert(&mut self, key: &[u8], value: &[u8]) {
-        let (nonce, ekey) = self.encode_key(key).expect("error encrypting key");
-        let evalue = self.encode_value(&nonce, value).expect("error encrypting value");
-        self.inner.insert(&ekey, &evalue)
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let ekey = self.encode_key(key)?;
+        let generation = self.generation;
+        let gk = self.value_keys(generation)?;
+
+        // Bump the write counter past whatever is already stored for this key, so overwriting an
+        // existing entry never reuses the value nonce it was sealed with.
+        let counter = match self.inner.get(&ekey)? {
+            Some(tagged) => {
+                let (_, counter, _) = split_entry(&tagged)?;
+                counter
+                    .checked_add(1)
+                    .ok_or_else(|| anyhow!("confidential store write counter exhausted"))?
+            }
+            None => 0,
+        };
+
+        let nonce = self.derive_value_nonce(gk, key, counter);
+        let evalue = self.encode_value(gk, &nonce, value)?;
+
+        let mut tagged = Vec::with_capacity(1 + 8 + evalue.len());
+        tagged.push(generation);
+        tagged.extend_from_slice(&counter.to_le_bytes());
+        tagged.extend_from_slice(&evalue);
+        self.inner.insert(&ekey, &tagged)
     }
 
-    fn remove(&mut self, key: &[u8]) {
-        let (_, ekey) = self.encode_key(key).expect("error encrypting key");
+    fn remove(&mut self, key: &[u8]) -> Result<(), Error> {
+        let ekey = self.encode_key(key)?;
         self.inner.remove(&ekey)
     }
 
-    fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
-        Box::new(ConfidentialStoreIterator::new(self))
+    fn iter(&self) -> Result<Box<dyn mkvs::Iterator + '_>, Error> {
+        Ok(Box::new(ConfidentialStoreIterator::new(self)?))
     }
 }
 
+/// An iterator over a `ConfidentialStore`.
+///
+/// Because keys and values are sealed independently of their neighbors, the inner mkvs iterator
+/// walks entries in ciphertext order, which bears no relation to the plaintext key order. As a
+/// consequence `seek` only supports positioning on an exact (encrypted) key and cannot be used to
+/// drive an ordered range scan.
 struct ConfidentialStoreIterator<'store, S: Store> {
     inner: Box<dyn mkvs::Iterator + 'store>,
     store: &'store ConfidentialStore<S>,
@@ -103,31 +262,57 @@ struct ConfidentialStoreIterator<'store, S: Store> {
 }
 
 impl<'store, S: Store> ConfidentialStoreIterator<'store, S> {
-    fn new(store: &'store ConfidentialStore<S>) -> ConfidentialStoreIterator<'_, S> {
-        ConfidentialStoreIterator {
-            inner: store.inner.iter(),
-            store: store,
-        }
+    fn new(store: &'store ConfidentialStore<S>) -> Result<ConfidentialStoreIterator<'_, S>, Error> {
+        let mut it = ConfidentialStoreIterator {
+            inner: store.inner.iter()?,
+            store,
+            key: None,
+            value: None,
+        };
+        it.reset();
+        Ok(it)
     }
 
+    /// Decrypts the entry the inner iterator currently points to and populates `key`/`value`, or
+    /// clears both if the inner iterator is no longer valid or the entry fails to decrypt.
     fn reset(&mut self) {
-        if self.inner.is_valid() {
-            match self.inner.get_key() {
-                None => {
-                    ()
-                }
-                _ => (),
-            }
-        } else {
-            self.key = None;
-            self.value = None;
+        self.key = None;
+        self.value = None;
+
+        if !self.inner.is_valid() {
+            return;
         }
+        let ekey = match self.inner.get_key() {
+            Some(ekey) => ekey.clone(),
+            None => return,
+        };
+        let tagged = match self.inner.get_value() {
+            Some(tagged) => tagged.clone(),
+            None => return,
+        };
+        let key = match self.store.decode_key(&ekey) {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        let (generation, counter, evalue) = match split_entry(&tagged) {
+            Ok(parts) => parts,
+            Err(_) => return,
+        };
+        let gk = match self.store.value_keys(generation) {
+            Ok(gk) => gk,
+            Err(_) => return,
+        };
+        let nonce = self.store.derive_value_nonce(gk, &key, counter);
+        let value = match self.store.decode_value(gk, &nonce, evalue) {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+
+        self.key = Some(key);
+        self.value = Some(value);
     }
 }
 
-impl<'store, S: Store> Iterator for ConfidentialStoreIterator<'store, S> {
-}
-
 impl<'store, S: Store> mkvs::Iterator for ConfidentialStoreIterator<'store, S> {
     fn set_prefetch(&mut self, prefetch: usize) {
         self.inner.set_prefetch(prefetch)
@@ -147,8 +332,9 @@ impl<'store, S: Store> mkvs::Iterator for ConfidentialStoreIterator<'store, S> {
     }
 
     fn seek(&mut self, key: &[u8]) {
-        let (_, ekey) = self.store.encode_key(key).expect("error encrypting key");
-        self.inner.seek(&ekey)
+        let ekey = self.store.encode_key(key).expect("error encrypting key");
+        self.inner.seek(&ekey);
+        self.reset();
     }
 
     fn get_key(&self) -> &Option<mkvs::Key> {
@@ -158,102 +344,10 @@ impl<'store, S: Store> mkvs::Iterator for ConfidentialStoreIterator<'store, S> {
     fn get_value(&self) -> &Option<Vec<u8>> {
         &self.value
     }
-}
-
-/*
-impl<S: Store, P: AsRef<[u8]>> Store for PrefixStore<S, P> {
-    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.parent.get(&[self.prefix.as_ref(), key].concat())
-    }
-
-    fn insert(&mut self, key: &[u8], value: &[u8]) {
-        self.parent
-            .insert(&[self.prefix.as_ref(), key].concat(), value);
-    }
-
-    fn remove(&mut self, key: &[u8]) {
-        self.parent.remove(&[self.prefix.as_ref(), key].concat());
-    }
-
-    fn iter(&self) -> Box<dyn mkvs::Iterator + '_> {
-        Box::new(PrefixStoreIterator::new(
-            self.parent.iter(),
-            self.prefix.as_ref(),
-        ))
-    }
-}
-
-/// An iterator over the `PrefixStore`.
-pub(crate) struct PrefixStoreIterator<'store> {
-    inner: Box<dyn mkvs::Iterator + 'store>,
-    prefix: &'store [u8],
-}
-
-impl<'store> PrefixStoreIterator<'store> {
-    fn new(mut inner: Box<dyn mkvs::Iterator + 'store>, prefix: &'store [u8]) -> Self {
-        inner.seek(prefix);
-        Self { inner, prefix }
-    }
-}
-
-impl<'store> Iterator for PrefixStoreIterator<'store> {
-    type Item = (Vec<u8>, Vec<u8>);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        Iterator::next(&mut self.inner).and_then(|(mut k, v)| {
-            if k.starts_with(self.prefix) {
-                Some((k.split_off(self.prefix.len()), v))
-            } else {
-                None
-            }
-        })
-    }
-}
-
-impl<'store> mkvs::Iterator for PrefixStoreIterator<'store> {
-    fn set_prefetch(&mut self, prefetch: usize) {
-        self.inner.set_prefetch(prefetch)
-    }
-
-    fn is_valid(&self) -> bool {
-        if !self
-            .inner
-            .get_key()
-            .as_ref()
-            .unwrap_or(&vec![])
-            .starts_with(self.prefix)
-        {
-            return false;
-        }
-        self.inner.is_valid()
-    }
-
-    fn error(&self) -> &Option<anyhow::Error> {
-        self.inner.error()
-    }
-
-    fn rewind(&mut self) {
-        self.inner.seek(self.prefix);
-    }
-
-    fn seek(&mut self, key: &[u8]) {
-        self.inner.seek(&[self.prefix, key].concat());
-    }
-
-    fn get_key(&self) -> &Option<mkvs::Key> {
-        self.inner.get_key()
-    }
-
-    fn get_value(&self) -> &Option<Vec<u8>> {
-        self.inner.get_value()
-    }
 
     fn next(&mut self) {
-        if !self.is_valid() {
-            // Could be invalid due to prefix mismatch.
-            return;
-        }
-        mkvs::Iterator::next(&mut *self.inner)
+        self.inner.next();
+        self.reset();
     }
 }
-*/
+
@@ -0,0 +1,123 @@
+//! Runtime storage abstractions.
+use anyhow::Error;
+
+use crate::core::storage::mkvs;
+
+mod confidential;
+
+pub use confidential::ConfidentialStore;
+
+/// A key-value store.
+///
+/// Implementations may be backed by the in-memory or mkvs-backed overlay, or may wrap another
+/// store to provide additional behavior (e.g. key prefixing or confidentiality). Errors are
+/// surfaced to the caller instead of panicking so that a single failing operation can fail the
+/// transaction that triggered it without taking down the whole batch.
+pub trait Store {
+    /// Fetch entry with given key.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Update entry with given key.
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error>;
+
+    /// Remove entry with given key.
+    fn remove(&mut self, key: &[u8]) -> Result<(), Error>;
+
+    /// Returns an iterator over the store.
+    fn iter(&self) -> Result<Box<dyn mkvs::Iterator + '_>, Error>;
+}
+
+/// A `Store` that prefixes all keys with a fixed prefix before forwarding to the parent store.
+pub struct PrefixStore<S: Store, P: AsRef<[u8]>> {
+    parent: S,
+    prefix: P,
+}
+
+impl<S: Store, P: AsRef<[u8]>> PrefixStore<S, P> {
+    /// Create a new `PrefixStore`.
+    pub fn new(parent: S, prefix: P) -> Self {
+        Self { parent, prefix }
+    }
+}
+
+impl<S: Store, P: AsRef<[u8]>> Store for PrefixStore<S, P> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.parent.get(&[self.prefix.as_ref(), key].concat())
+    }
+
+    fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.parent
+            .insert(&[self.prefix.as_ref(), key].concat(), value)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.parent.remove(&[self.prefix.as_ref(), key].concat())
+    }
+
+    fn iter(&self) -> Result<Box<dyn mkvs::Iterator + '_>, Error> {
+        Ok(Box::new(PrefixStoreIterator::new(
+            self.parent.iter()?,
+            self.prefix.as_ref(),
+        )))
+    }
+}
+
+/// An iterator over the `PrefixStore`.
+pub(crate) struct PrefixStoreIterator<'store> {
+    inner: Box<dyn mkvs::Iterator + 'store>,
+    prefix: &'store [u8],
+}
+
+impl<'store> PrefixStoreIterator<'store> {
+    fn new(mut inner: Box<dyn mkvs::Iterator + 'store>, prefix: &'store [u8]) -> Self {
+        inner.seek(prefix);
+        Self { inner, prefix }
+    }
+}
+
+impl<'store> mkvs::Iterator for PrefixStoreIterator<'store> {
+    fn set_prefetch(&mut self, prefetch: usize) {
+        self.inner.set_prefetch(prefetch)
+    }
+
+    fn is_valid(&self) -> bool {
+        if !self
+            .inner
+            .get_key()
+            .as_ref()
+            .unwrap_or(&vec![])
+            .starts_with(self.prefix)
+        {
+            return false;
+        }
+        self.inner.is_valid()
+    }
+
+    fn error(&self) -> Option<Error> {
+        self.inner.error()
+    }
+
+    fn rewind(&mut self) {
+        self.inner.seek(self.prefix);
+    }
+
+    fn seek(&mut self, key: &[u8]) {
+        self.inner.seek(&[self.prefix, key].concat());
+    }
+
+    fn get_key(&self) -> &Option<mkvs::Key> {
+        self.inner.get_key()
+    }
+
+    fn get_value(&self) -> &Option<Vec<u8>> {
+        self.inner.get_value()
+    }
+
+    fn next(&mut self) {
+        if !self.is_valid() {
+            // Could be invalid due to prefix mismatch.
+            return;
+        }
+        self.inner.next()
+    }
+}
@@ -0,0 +1,179 @@
+//! Transaction types.
+use thiserror::Error;
+
+use crate::types::{address::SignatureAddressSpec, token};
+
+/// The latest transaction format version that is accepted unconditionally, regardless of module
+/// configuration.
+pub const LATEST_TRANSACTION_VERSION: u32 = 1;
+
+/// The transaction format version that adds a validity window (`not_before`/`not_after`) to the
+/// transaction envelope. Unlike `LATEST_TRANSACTION_VERSION`, transactions using this version are
+/// only accepted once explicitly enabled by the dispatching module's parameters (see
+/// `Transaction::validate_version`), so that existing deployments keep rejecting it until they
+/// opt in.
+pub const TRANSACTION_VERSION_V2: u32 = 2;
+
+/// A weight used for accounting how much of a per-block resource a transaction consumes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, cbor::Encode, cbor::Decode)]
+pub enum TransactionWeight {
+    /// Weight for consensus messages emitted by the transaction.
+    ConsensusMessages,
+}
+
+/// An authentication mechanism for a transaction signer.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+#[cbor(untagged)]
+pub enum AddressSpec {
+    /// Direct address derived from a signature scheme.
+    Signature(SignatureAddressSpec),
+}
+
+/// Transaction call format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, cbor::Encode, cbor::Decode)]
+pub enum CallFormat {
+    /// Plaintext call, dispatched as-is.
+    Plain = 0,
+    /// Call encrypted with X25519 key exchange and DeoxysII sealing.
+    EncryptedX25519DeoxysII = 1,
+}
+
+/// A method call.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct Call {
+    /// Format used for the call body.
+    pub format: CallFormat,
+    /// Method name to dispatch to.
+    pub method: String,
+    /// Call body, in the encoding specified by `format`.
+    pub body: cbor::Value,
+}
+
+/// A transaction fee.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Fee {
+    /// Amount paid as the fee.
+    pub amount: token::BaseUnits,
+    /// Maximum gas the transaction is allowed to use.
+    pub gas: u64,
+    /// Number of consensus messages the transaction is allowed to emit.
+    pub consensus_messages: u32,
+}
+
+/// Information about a transaction signer.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+pub struct SignerInfo {
+    /// The mechanism used to authenticate this signer.
+    pub address_spec: AddressSpec,
+    /// The signer's nonce for this transaction.
+    pub nonce: u64,
+}
+
+impl SignerInfo {
+    /// Creates `SignerInfo` for a signer authenticated directly via a signature scheme.
+    pub fn new_sigspec(spec: SignatureAddressSpec, nonce: u64) -> Self {
+        Self {
+            address_spec: AddressSpec::Signature(spec),
+            nonce,
+        }
+    }
+}
+
+/// Transaction authentication information.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct AuthInfo {
+    /// Information about each of the transaction's signers, in signing order.
+    pub signer_info: Vec<SignerInfo>,
+    /// The fee the signers are willing to pay to have the transaction included.
+    pub fee: Fee,
+}
+
+/// A runtime transaction.
+///
+/// Version 1 is exactly the `version`/`call`/`auth_info` triple that has always been accepted.
+/// Version 2 additionally carries a validity window (`not_before`/`not_after`, each an optional
+/// consensus round bound); on a version-1 transaction these are always absent, so decoding a
+/// version-1 wire transaction yields the same `Transaction` as before this change, just with both
+/// fields set to `None`.
+#[derive(Clone, Debug, Default, cbor::Encode, cbor::Decode)]
+pub struct Transaction {
+    /// Format version of this transaction.
+    pub version: u32,
+    /// The method call this transaction carries.
+    pub call: Call,
+    /// Authentication information for the transaction signers.
+    pub auth_info: AuthInfo,
+    /// Earliest consensus round at which this transaction may be included. Only meaningful (and
+    /// only ever non-`None`) for transactions using `TRANSACTION_VERSION_V2` or later.
+    #[cbor(default)]
+    pub not_before: Option<u64>,
+    /// Latest consensus round at which this transaction may be included. Only meaningful (and
+    /// only ever non-`None`) for transactions using `TRANSACTION_VERSION_V2` or later.
+    #[cbor(default)]
+    pub not_after: Option<u64>,
+}
+
+/// Errors raised while validating a transaction's format version against the dispatching
+/// module's configuration.
+#[derive(Error, Debug)]
+pub enum TransactionVersionError {
+    #[error("unknown transaction format version {0}")]
+    Unknown(u32),
+
+    #[error("transaction format version {0} is not enabled")]
+    NotEnabled(u32),
+}
+
+impl Transaction {
+    /// Validates `self.version` against the set of format versions the dispatcher currently
+    /// accepts. `allow_version_2` mirrors the dispatching module's parameter that gates
+    /// `TRANSACTION_VERSION_V2` acceptance; it defaults to off, so a fresh deployment keeps
+    /// rejecting version-2 transactions until the parameter is explicitly turned on.
+    pub fn validate_version(&self, allow_version_2: bool) -> Result<(), TransactionVersionError> {
+        match self.version {
+            LATEST_TRANSACTION_VERSION => Ok(()),
+            TRANSACTION_VERSION_V2 if allow_version_2 => Ok(()),
+            TRANSACTION_VERSION_V2 => Err(TransactionVersionError::NotEnabled(self.version)),
+            version => Err(TransactionVersionError::Unknown(version)),
+        }
+    }
+
+    /// Decodes a transaction from its CBOR wire representation and validates its format version
+    /// against `allow_version_2`. This is the entry point the dispatcher must use for any
+    /// externally-submitted transaction: decoding alone would accept `TRANSACTION_VERSION_V2`
+    /// unconditionally (its fields all have `#[cbor(default)]`), so the gate has to be applied
+    /// here rather than left to callers to remember.
+    pub fn decode(data: &[u8], allow_version_2: bool) -> Result<Self, TransactionDecodeError> {
+        let tx: Self = cbor::from_slice(data)?;
+        tx.validate_version(allow_version_2)?;
+        Ok(tx)
+    }
+}
+
+/// Errors raised while decoding a transaction from its wire representation.
+#[derive(Error, Debug)]
+pub enum TransactionDecodeError {
+    #[error("malformed transaction: {0}")]
+    Malformed(#[from] cbor::Error),
+
+    #[error(transparent)]
+    Version(#[from] TransactionVersionError),
+}
+
+/// The outcome of a dispatched call, as recorded in the runtime's per-transaction event tag.
+#[derive(Clone, Debug, cbor::Encode, cbor::Decode)]
+#[cbor(untagged)]
+pub enum CallResult {
+    /// The call completed successfully, carrying its result value.
+    Ok(cbor::Value),
+    /// The call failed, with the module and error code that rejected it.
+    Failed {
+        /// The runtime module that generated the reversion.
+        module: String,
+        /// The runtime error code.
+        code: u32,
+        /// The error message, if provided by the module.
+        #[cbor(default)]
+        message: Option<String>,
+    },
+}